@@ -0,0 +1,177 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Memory barriers for ordering accesses to devices and to memory shared
+//! with devices
+//!
+//! Volatile accesses are not reordered by the compiler, but the CPU may
+//! still reorder them with other accesses, especially on cached mappings.
+//! The typical case is a DMA descriptor written to a buffer before ringing
+//! the doorbell register of the device:
+//!
+//! ```no_run
+//! use devmem::dma::DmaBuffer;
+//! use devmem::Mapping;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mut descriptors = DmaBuffer::hugepage(4096)?;
+//! let mut regs = unsafe { Mapping::new(0x4000_0000, 0x100)? };
+//! descriptors.write_u64(0x0, 0x1000)?;
+//! descriptors.flush_cache(0x0, 8)?;
+//! regs.write_barrier();
+//! regs.write_u32(0x10, 1)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! | Barrier | x86, x86_64 | aarch64     | arm         | riscv              |
+//! |---------|-------------|-------------|-------------|--------------------|
+//! | `read`  | `lfence`    | `dmb oshld` | `dmb osh`   | `fence ir, ir`     |
+//! | `write` | `sfence`    | `dmb oshst` | `dmb oshst` | `fence ow, ow`     |
+//! | `full`  | `mfence`    | `dsb sy`    | `dsb sy`    | `fence iorw, iorw` |
+//!
+//! On other architectures all three fall back to a sequentially consistent
+//! atomic fence.
+
+use std::sync::atomic::{self, Ordering};
+
+/// Read barrier: the reads before it are performed before the reads after
+/// it
+#[inline]
+pub fn read() {
+    atomic::compiler_fence(Ordering::SeqCst);
+    arch::read();
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// Write barrier: the writes before it are observed by devices before the
+/// writes after it
+#[inline]
+pub fn write() {
+    atomic::compiler_fence(Ordering::SeqCst);
+    arch::write();
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// Full barrier: all the memory accesses before it complete before any
+/// access after it
+#[inline]
+pub fn full() {
+    atomic::compiler_fence(Ordering::SeqCst);
+    arch::full();
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{_mm_lfence, _mm_mfence, _mm_sfence};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{_mm_lfence, _mm_mfence, _mm_sfence};
+
+    #[inline]
+    pub fn read() {
+        unsafe { _mm_lfence() }
+    }
+
+    #[inline]
+    pub fn write() {
+        unsafe { _mm_sfence() }
+    }
+
+    #[inline]
+    pub fn full() {
+        unsafe { _mm_mfence() }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use std::arch::asm;
+
+    #[inline]
+    pub fn read() {
+        unsafe { asm!("dmb oshld", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn write() {
+        unsafe { asm!("dmb oshst", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn full() {
+        unsafe { asm!("dsb sy", options(nostack, preserves_flags)) }
+    }
+}
+
+#[cfg(target_arch = "arm")]
+mod arch {
+    use std::arch::asm;
+
+    /// ARMv7 has no barrier limited to loads
+    #[inline]
+    pub fn read() {
+        unsafe { asm!("dmb osh", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn write() {
+        unsafe { asm!("dmb oshst", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn full() {
+        unsafe { asm!("dsb sy", options(nostack, preserves_flags)) }
+    }
+}
+
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+mod arch {
+    use std::arch::asm;
+
+    #[inline]
+    pub fn read() {
+        unsafe { asm!("fence ir, ir", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn write() {
+        unsafe { asm!("fence ow, ow", options(nostack, preserves_flags)) }
+    }
+
+    #[inline]
+    pub fn full() {
+        unsafe { asm!("fence iorw, iorw", options(nostack, preserves_flags)) }
+    }
+}
+
+#[cfg(not(any(
+    target_arch = "x86",
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "arm",
+    target_arch = "riscv32",
+    target_arch = "riscv64"
+)))]
+mod arch {
+    use std::sync::atomic::{self, Ordering};
+
+    #[inline]
+    pub fn read() {
+        atomic::fence(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn write() {
+        atomic::fence(Ordering::SeqCst)
+    }
+
+    #[inline]
+    pub fn full() {
+        atomic::fence(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,575 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Batches of accesses at scattered physical addresses
+//!
+//! A `Batch` maps each run of adjacent pages touched by its operations once,
+//! instead of mapping the device for every access, then performs the
+//! operations in order.
+//!
+//! ```no_run
+//! use devmem::batch::Batch;
+//! use std::time::Duration;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mut batch = Batch::new();
+//! batch
+//!     .write(0x4000_0000, 0x1u32)
+//!     .set(0x4000_0004, 0x10u32)
+//!     .poll(0x4000_0008, 0x1u32, 0x1, Duration::from_millis(10))
+//!     .delay(Duration::from_micros(100))
+//!     .read::<u32>(0x4800_0000);
+//! let values = unsafe { batch.run()? };
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! With the `init-table` feature, batches can also be loaded from a TOML
+//! init table, with an `[[op]]` table for each operation:
+//!
+//! ```toml
+//! [[op]]
+//! write = 0x4000_0000
+//! value = 0x1
+//!
+//! [[op]]
+//! clear = 0x4000_0004
+//! width = 16
+//! bits = 0x8000
+//!
+//! [[op]]
+//! poll = 0x4000_0008
+//! mask = 0x1
+//! value = 0x1
+//! timeout_us = 10000
+//!
+//! [[op]]
+//! delay_us = 100
+//! ```
+//!
+//! Each operation has one of the keys `write`, `read`, `set`, `clear` and
+//! `poll` with the address, or `delay_us`. The access width is given in bits
+//! and is 32 by default. Numbers that do not fit in a TOML integer can be
+//! written as strings, like `"0xffff_ffff_ffff_ffff"`.
+
+use crate::poll::Poller;
+use crate::{Error, Mapping, MemoryWrite, Result, Word};
+#[cfg(feature = "init-table")]
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Operation of a batch
+///
+/// The `width` of the accesses is in bytes, and must be 1, 2, 4 or 8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Write `value`
+    Write {
+        address: usize,
+        width: usize,
+        value: u64,
+    },
+    /// Read a value, returned by `Batch::run`
+    Read { address: usize, width: usize },
+    /// Set `bits` with a read-modify-write
+    Set {
+        address: usize,
+        width: usize,
+        bits: u64,
+    },
+    /// Clear `bits` with a read-modify-write
+    Clear {
+        address: usize,
+        width: usize,
+        bits: u64,
+    },
+    /// Wait until the bits selected by `mask` are equal to the same bits of
+    /// `value`
+    Poll {
+        address: usize,
+        width: usize,
+        mask: u64,
+        value: u64,
+        timeout: Duration,
+    },
+    /// Sleep
+    Delay(Duration),
+}
+
+impl Operation {
+    /// Address and width of the access, if any
+    fn access(&self) -> Option<(usize, usize)> {
+        match *self {
+            Operation::Write { address, width, .. }
+            | Operation::Read { address, width }
+            | Operation::Set { address, width, .. }
+            | Operation::Clear { address, width, .. }
+            | Operation::Poll { address, width, .. } => Some((address, width)),
+            Operation::Delay(_) => None,
+        }
+    }
+}
+
+/// Value read by a `Operation::Read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadValue {
+    pub address: usize,
+    /// Width of the access in bytes
+    pub width: usize,
+    pub value: u64,
+}
+
+/// Sequence of operations on the physical address space
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Batch {
+    device: PathBuf,
+    operations: Vec<Operation>,
+}
+
+impl Default for Batch {
+    fn default() -> Batch {
+        Batch {
+            device: PathBuf::from("/dev/mem"),
+            operations: Vec::new(),
+        }
+    }
+}
+
+impl Batch {
+    /// Create an empty batch on `/dev/mem`
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    /// Set the path of the device to map, where the addresses are offsets
+    pub fn device<P: AsRef<Path>>(&mut self, path: P) -> &mut Batch {
+        self.device = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Operations of the batch
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Append an operation
+    pub fn push(&mut self, operation: Operation) -> &mut Batch {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Append a write of `value` at `address`
+    pub fn write<T: Word>(&mut self, address: usize, value: T) -> &mut Batch {
+        self.push(Operation::Write {
+            address,
+            width: T::SIZE,
+            value: value.to_u64(),
+        })
+    }
+
+    /// Append a read of a `T` at `address`
+    pub fn read<T: Word>(&mut self, address: usize) -> &mut Batch {
+        self.push(Operation::Read {
+            address,
+            width: T::SIZE,
+        })
+    }
+
+    /// Append a read-modify-write setting `bits` of the `T` at `address`
+    pub fn set<T: Word>(&mut self, address: usize, bits: T) -> &mut Batch {
+        self.push(Operation::Set {
+            address,
+            width: T::SIZE,
+            bits: bits.to_u64(),
+        })
+    }
+
+    /// Append a read-modify-write clearing `bits` of the `T` at `address`
+    pub fn clear<T: Word>(&mut self, address: usize, bits: T) -> &mut Batch {
+        self.push(Operation::Clear {
+            address,
+            width: T::SIZE,
+            bits: bits.to_u64(),
+        })
+    }
+
+    /// Append a wait until the bits of the `T` at `address` selected by
+    /// `mask` are equal to the same bits of `value`
+    pub fn poll<T: Word>(
+        &mut self,
+        address: usize,
+        mask: T,
+        value: T,
+        timeout: Duration,
+    ) -> &mut Batch {
+        self.push(Operation::Poll {
+            address,
+            width: T::SIZE,
+            mask: mask.to_u64(),
+            value: value.to_u64(),
+            timeout,
+        })
+    }
+
+    /// Append a sleep of `duration`
+    pub fn delay(&mut self, duration: Duration) -> &mut Batch {
+        self.push(Operation::Delay(duration))
+    }
+
+    /// Ranges of pages mapped to run the batch, with their start address and
+    /// length
+    ///
+    /// Adjacent and overlapping pages are merged into a single range. Fails
+    /// with `Error::Unsupported` if an operation has an invalid width.
+    pub fn mapped_ranges(&self) -> Result<Vec<(usize, usize)>> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        let mut pages = Vec::new();
+        for (address, width) in self.operations.iter().filter_map(Operation::access) {
+            check_width(address, width)?;
+            let end = address.checked_add(width).ok_or(Error::Overflow)?;
+            let start = address - address % page_size;
+            let end =
+                end.checked_add(page_size - 1).ok_or(Error::Overflow)? / page_size * page_size;
+            pages.push((start, end));
+        }
+        pages.sort_unstable();
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in pages {
+            match ranges.last_mut() {
+                Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+        Ok(ranges
+            .into_iter()
+            .map(|(start, end)| (start, end - start))
+            .collect())
+    }
+
+    /// Map the device and run the operations in order
+    ///
+    /// Each range returned by `mapped_ranges` is mapped once. Returns the
+    /// values read by the `Operation::Read` operations, and stops at the
+    /// first failing operation. No operation is run if one of them has an
+    /// invalid width.
+    ///
+    /// # Safety
+    ///
+    /// See `Mapping::new`.
+    pub unsafe fn run(&self) -> Result<Vec<ReadValue>> {
+        let mut mappings = Vec::new();
+        for (start, len) in self.mapped_ranges()? {
+            let mapping = Mapping::builder()
+                .device(&self.device)
+                .offset(start)
+                .len(len)
+                .map()?;
+            mappings.push((start, mapping));
+        }
+
+        let mut values = Vec::new();
+        for operation in &self.operations {
+            let address = match operation.access() {
+                Some((address, _)) => address,
+                None => {
+                    if let Operation::Delay(duration) = *operation {
+                        thread::sleep(duration);
+                    }
+                    continue;
+                }
+            };
+            // The ranges are sorted, so the mapping containing the address is
+            // the last one starting before it
+            let index = mappings.partition_point(|(start, _)| *start <= address) - 1;
+            let (start, mapping) = &mut mappings[index];
+            run_operation(operation, mapping, *start, &mut values)?;
+        }
+        Ok(values)
+    }
+
+    /// Run the operations in order on `memory`, whose offset 0 is at
+    /// `base`
+    ///
+    /// Used to run a batch on a simulated memory or on an existing mapping.
+    /// No operation is run if one of them has an invalid width.
+    pub fn run_on<M: MemoryWrite + ?Sized>(
+        &self,
+        memory: &mut M,
+        base: usize,
+    ) -> Result<Vec<ReadValue>> {
+        for (address, width) in self.operations.iter().filter_map(Operation::access) {
+            check_width(address, width)?;
+        }
+        let mut values = Vec::new();
+        for operation in &self.operations {
+            run_operation(operation, memory, base, &mut values)?;
+        }
+        Ok(values)
+    }
+}
+
+/// Run `operation` on `memory`, whose offset 0 is at `base`
+fn run_operation<M: MemoryWrite + ?Sized>(
+    operation: &Operation,
+    memory: &mut M,
+    base: usize,
+    values: &mut Vec<ReadValue>,
+) -> Result<()> {
+    let offset = |address: usize, width: usize| {
+        address.checked_sub(base).ok_or(Error::OutOfBounds {
+            offset: 0,
+            len: width,
+        })
+    };
+    match *operation {
+        Operation::Write {
+            address,
+            width,
+            value,
+        } => write_width(memory, address, offset(address, width)?, width, value),
+        Operation::Read { address, width } => {
+            let value = read_width(memory, address, offset(address, width)?, width)?;
+            values.push(ReadValue {
+                address,
+                width,
+                value,
+            });
+            Ok(())
+        }
+        Operation::Set {
+            address,
+            width,
+            bits,
+        } => {
+            let offset = offset(address, width)?;
+            let value = read_width(memory, address, offset, width)?;
+            write_width(memory, address, offset, width, value | bits)
+        }
+        Operation::Clear {
+            address,
+            width,
+            bits,
+        } => {
+            let offset = offset(address, width)?;
+            let value = read_width(memory, address, offset, width)?;
+            write_width(memory, address, offset, width, value & !bits)
+        }
+        Operation::Poll {
+            address,
+            width,
+            mask,
+            value,
+            timeout,
+        } => {
+            let offset = offset(address, width)?;
+            let poller = Poller::new(timeout);
+            match width {
+                1 => poller
+                    .poll_until(memory, offset, mask as u8, value as u8)
+                    .map(drop),
+                2 => poller
+                    .poll_until(memory, offset, mask as u16, value as u16)
+                    .map(drop),
+                4 => poller
+                    .poll_until(memory, offset, mask as u32, value as u32)
+                    .map(drop),
+                8 => poller.poll_until(memory, offset, mask, value).map(drop),
+                _ => Err(invalid_width(address, width)),
+            }
+        }
+        Operation::Delay(duration) => {
+            thread::sleep(duration);
+            Ok(())
+        }
+    }
+}
+
+/// Read `width` bytes at `offset`, the offset of `address` in `memory`
+fn read_width<M: MemoryWrite + ?Sized>(
+    memory: &M,
+    address: usize,
+    offset: usize,
+    width: usize,
+) -> Result<u64> {
+    match width {
+        1 => memory.read_u8(offset).map(u64::from),
+        2 => memory.read_u16(offset).map(u64::from),
+        4 => memory.read_u32(offset).map(u64::from),
+        8 => memory.read_u64(offset),
+        _ => Err(invalid_width(address, width)),
+    }
+}
+
+/// Write `width` bytes at `offset`, the offset of `address` in `memory`
+fn write_width<M: MemoryWrite + ?Sized>(
+    memory: &mut M,
+    address: usize,
+    offset: usize,
+    width: usize,
+    value: u64,
+) -> Result<()> {
+    match width {
+        1 => memory.write_u8(offset, value as u8),
+        2 => memory.write_u16(offset, value as u16),
+        4 => memory.write_u32(offset, value as u32),
+        8 => memory.write_u64(offset, value),
+        _ => Err(invalid_width(address, width)),
+    }
+}
+
+fn check_width(address: usize, width: usize) -> Result<()> {
+    match width {
+        1 | 2 | 4 | 8 => Ok(()),
+        _ => Err(invalid_width(address, width)),
+    }
+}
+
+/// Error for an access of `width` bytes at `address`, which is not the width
+/// of a `Word`
+fn invalid_width(address: usize, width: usize) -> Error {
+    Error::Unsupported(format!(
+        "invalid access width of {} bytes at {:#x}, expected 1, 2, 4 or 8",
+        width, address
+    ))
+}
+
+#[cfg(feature = "init-table")]
+impl Batch {
+    /// Load an init table
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Batch> {
+        let text = std::fs::read_to_string(path).map_err(Error::Io)?;
+        Batch::parse(&text)
+    }
+
+    /// Parse the content of an init table
+    pub fn parse(text: &str) -> Result<Batch> {
+        let table: toml::Table = text
+            .parse()
+            .map_err(|err| Error::Parse(format!("init table: {}", err)))?;
+
+        let mut batch = Batch::new();
+        for (key, value) in &table {
+            let operations = match (key.as_str(), value) {
+                ("op", toml::Value::Array(operations)) => operations,
+                _ => return Err(Error::Parse(format!("init table: invalid key {:?}", key))),
+            };
+            for (index, operation) in operations.iter().enumerate() {
+                let operation = operation
+                    .as_table()
+                    .ok_or_else(|| "expected a table".to_string())
+                    .and_then(parse_operation)
+                    .map_err(|message| {
+                        Error::Parse(format!("init table: op {}: {}", index + 1, message))
+                    })?;
+                batch.push(operation);
+            }
+        }
+        Ok(batch)
+    }
+}
+
+#[cfg(feature = "init-table")]
+fn parse_operation(table: &toml::Table) -> std::result::Result<Operation, String> {
+    const KINDS: [&str; 6] = ["write", "read", "set", "clear", "poll", "delay_us"];
+    let mut kinds = KINDS.iter().filter(|kind| table.contains_key(**kind));
+    let kind = match (kinds.next(), kinds.next()) {
+        (Some(kind), None) => *kind,
+        _ => return Err(format!("expected exactly one of {}", KINDS.join(", "))),
+    };
+    let allowed: &[&str] = match kind {
+        "write" => &["value", "width"],
+        "set" | "clear" => &["bits", "width"],
+        "poll" => &["mask", "value", "timeout_us", "width"],
+        "read" => &["width"],
+        _ => &[],
+    };
+    if let Some(key) = table
+        .keys()
+        .find(|key| key.as_str() != kind && !allowed.contains(&key.as_str()))
+    {
+        return Err(format!("invalid key {:?} for {}", key, kind));
+    }
+
+    let number = |key: &str| -> std::result::Result<u64, String> {
+        let value = table
+            .get(key)
+            .ok_or_else(|| format!("missing key {:?}", key))?;
+        parse_number(value).ok_or_else(|| format!("invalid {} {}", key, value))
+    };
+    if kind == "delay_us" {
+        return Ok(Operation::Delay(Duration::from_micros(number(kind)?)));
+    }
+
+    let address = usize::try_from(number(kind)?).map_err(|_| "address too large".to_string())?;
+    let width = match table.get("width") {
+        None => 4,
+        Some(width) => match parse_number(width) {
+            Some(8) => 1,
+            Some(16) => 2,
+            Some(32) => 4,
+            Some(64) => 8,
+            _ => return Err(format!("invalid width {}, expected 8, 16, 32 or 64", width)),
+        },
+    };
+    let fitting = |key: &str| {
+        let value = number(key)?;
+        if width < 8 && value >> (width * 8) != 0 {
+            return Err(format!(
+                "{} {:#x} does not fit in {} bits",
+                key,
+                value,
+                width * 8
+            ));
+        }
+        Ok(value)
+    };
+
+    Ok(match kind {
+        "write" => Operation::Write {
+            address,
+            width,
+            value: fitting("value")?,
+        },
+        "read" => Operation::Read { address, width },
+        "set" => Operation::Set {
+            address,
+            width,
+            bits: fitting("bits")?,
+        },
+        "clear" => Operation::Clear {
+            address,
+            width,
+            bits: fitting("bits")?,
+        },
+        _ => Operation::Poll {
+            address,
+            width,
+            mask: fitting("mask")?,
+            value: fitting("value")?,
+            timeout: Duration::from_micros(number("timeout_us")?),
+        },
+    })
+}
+
+/// Parse a non-negative integer, or a string with a decimal or hexadecimal
+/// number for the values that do not fit in a TOML integer
+#[cfg(feature = "init-table")]
+fn parse_number(value: &toml::Value) -> Option<u64> {
+    match value {
+        toml::Value::Integer(value) => u64::try_from(*value).ok(),
+        toml::Value::String(text) => {
+            let text = text.replace('_', "");
+            match text.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16).ok(),
+                None => text.parse().ok(),
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,368 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Read and write the physical address space from the command line
+
+#[cfg(feature = "init-table")]
+use devmem::batch::Batch;
+use devmem::trace::{self, TraceReader};
+use devmem::{image, Error, Mapping, MappingBuilder, ReadOnlyMapping};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "\
+Usage: devmem [-d DEVICE] COMMAND [ARGS]
+
+Commands:
+    read ADDRESS [WIDTH]           Read an 8/16/32/64-bit value (default 32)
+    write ADDRESS WIDTH VALUE      Write an 8/16/32/64-bit value
+    dump ADDRESS LENGTH            Dump a range as hexdump
+    fill ADDRESS LENGTH BYTE       Fill a range with a byte
+    copy SOURCE DEST LENGTH        Copy a range to another address
+    load ADDRESS FILE              Write the content of a file to memory
+    save ADDRESS LENGTH FILE       Save a range to a file
+    load-image FILE                Load an Intel HEX or S-record image
+    save-image ADDRESS LENGTH FILE Save a range as an Intel HEX or S-record
+                                   image, depending on the file extension
+    replay FILE                    Re-issue the accesses of a binary trace and
+                                   report the reads returning other values
+    batch FILE                     Run the operations of a TOML init table and
+                                   print the values read
+    ADDRESS [WIDTH [VALUE]]        Read or write a value, like busybox devmem
+
+Options:
+    -d, --device DEVICE            Map DEVICE instead of /dev/mem
+
+Numbers can be given in decimal or in hexadecimal with the 0x prefix.";
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let mut device = String::from("/dev/mem");
+    if matches!(
+        args.first().map(String::as_str),
+        Some("-d") | Some("--device")
+    ) {
+        if args.len() < 2 {
+            usage();
+        }
+        device = args.remove(1);
+        args.remove(0);
+    }
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let tool = Tool { device };
+    let result = match args.as_slice() {
+        ["read", addr] => tool.read(number(addr), 32),
+        ["read", addr, width] => tool.read(number(addr), width_bits(width)),
+        ["write", addr, width, value] => {
+            tool.write(number(addr), width_bits(width), parse_value(value))
+        }
+        ["dump", addr, len] => tool.dump(number(addr), number(len)),
+        ["fill", addr, len, byte] => tool.fill(number(addr), number(len), number(byte)),
+        ["copy", src, dst, len] => tool.copy(number(src), number(dst), number(len)),
+        ["load", addr, file] => tool.load(number(addr), file),
+        ["save", addr, len, file] => tool.save(number(addr), number(len), file),
+        ["load-image", file] => tool.load_image(file),
+        ["save-image", addr, len, file] => tool.save_image(number(addr), number(len), file),
+        ["replay", file] => tool.replay(file),
+        ["batch", file] => tool.batch(file),
+        [addr] if parse_number(addr).is_some() => tool.read(number(addr), 32),
+        [addr, width] if parse_number(addr).is_some() => tool.read(number(addr), width_bits(width)),
+        [addr, width, value] if parse_number(addr).is_some() => {
+            tool.write(number(addr), width_bits(width), parse_value(value))
+        }
+        _ => usage(),
+    };
+
+    if let Err(err) = result {
+        eprintln!("devmem: {}", err);
+        process::exit(1);
+    }
+}
+
+/// Error of a command, with a hint for the most common failures
+struct CommandError(String);
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Error> for CommandError {
+    fn from(err: Error) -> CommandError {
+        let hint = match &err {
+            Error::Open(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                " (root privileges are required)"
+            }
+            Error::Misaligned { .. } => " (the address must be aligned to the access width)",
+            _ => "",
+        };
+        CommandError(format!("{}{}", err, hint))
+    }
+}
+
+impl From<io::Error> for CommandError {
+    fn from(err: io::Error) -> CommandError {
+        CommandError(err.to_string())
+    }
+}
+
+type CommandResult = Result<(), CommandError>;
+
+struct Tool {
+    device: String,
+}
+
+impl Tool {
+    fn builder(&self, addr: usize, len: usize) -> MappingBuilder {
+        Mapping::builder()
+            .device(&self.device)
+            .offset(addr)
+            .len(len)
+    }
+
+    fn map(&self, addr: usize, len: usize) -> Result<Mapping, CommandError> {
+        Ok(unsafe { self.builder(addr, len).map()? })
+    }
+
+    fn map_readonly(&self, addr: usize, len: usize) -> Result<ReadOnlyMapping, CommandError> {
+        Ok(unsafe { self.builder(addr, len).map_readonly()? })
+    }
+
+    fn read(&self, addr: usize, bits: usize) -> CommandResult {
+        check_aligned(addr, bits / 8)?;
+        let map = self.map_readonly(addr, bits / 8)?;
+        let value = match bits {
+            8 => u64::from(map.read_u8(0)?),
+            16 => u64::from(map.read_u16(0)?),
+            32 => u64::from(map.read_u32(0)?),
+            _ => map.read_u64(0)?,
+        };
+        println!("0x{:0width$X}", value, width = bits / 4);
+        Ok(())
+    }
+
+    fn write(&self, addr: usize, bits: usize, value: u64) -> CommandResult {
+        if bits < 64 && value >> bits != 0 {
+            return Err(CommandError(format!(
+                "value {:#x} does not fit in {} bits",
+                value, bits
+            )));
+        }
+        check_aligned(addr, bits / 8)?;
+        let mut map = self.map(addr, bits / 8)?;
+        match bits {
+            8 => map.write_u8(0, value as u8)?,
+            16 => map.write_u16(0, value as u16)?,
+            32 => map.write_u32(0, value as u32)?,
+            _ => map.write_u64(0, value)?,
+        }
+        Ok(())
+    }
+
+    fn dump(&self, addr: usize, len: usize) -> CommandResult {
+        let map = self.map_readonly(addr, len)?;
+        let mut data = vec![0; len];
+        map.copy_into_slice(&mut data);
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        for (i, line) in data.chunks(16).enumerate() {
+            write!(out, "{:08x}: ", addr + i * 16)?;
+            for column in 0..16 {
+                match line.get(column) {
+                    Some(byte) => write!(out, "{:02x} ", byte)?,
+                    None => write!(out, "   ")?,
+                }
+            }
+            let ascii: String = line
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            writeln!(out, " |{}|", ascii)?;
+        }
+        Ok(())
+    }
+
+    fn fill(&self, addr: usize, len: usize, byte: usize) -> CommandResult {
+        if byte > 0xff {
+            return Err(CommandError(format!(
+                "fill value {:#x} is not a byte",
+                byte
+            )));
+        }
+        let mut map = self.map(addr, len)?;
+        map.copy_from_slice(&vec![byte as u8; len]);
+        Ok(())
+    }
+
+    fn copy(&self, src: usize, dst: usize, len: usize) -> CommandResult {
+        let mut data = vec![0; len];
+        self.map_readonly(src, len)?.copy_into_slice(&mut data);
+        self.map(dst, len)?.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn load(&self, addr: usize, file: &str) -> CommandResult {
+        let data = std::fs::read(file)?;
+        self.map(addr, data.len())?.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn save(&self, addr: usize, len: usize, file: &str) -> CommandResult {
+        let mut data = vec![0; len];
+        self.map_readonly(addr, len)?.copy_into_slice(&mut data);
+        std::fs::write(file, &data)?;
+        Ok(())
+    }
+
+    fn load_image(&self, file: &str) -> CommandResult {
+        let text = std::fs::read_to_string(file)?;
+        let segments = image::parse(image_format(file)?, &text)
+            .map_err(|err| CommandError(format!("{}: {}", file, err)))?;
+        for segment in segments.iter().filter(|s| !s.data.is_empty()) {
+            self.map(segment.address, segment.data.len())?
+                .copy_from_slice(&segment.data);
+        }
+        Ok(())
+    }
+
+    fn save_image(&self, addr: usize, len: usize, file: &str) -> CommandResult {
+        let format = image_format(file)?;
+        let mut data = vec![0; len];
+        self.map_readonly(addr, len)?.copy_into_slice(&mut data);
+        let out = io::BufWriter::new(std::fs::File::create(file)?);
+        match format {
+            image::Format::IntelHex => image::write_ihex(out, addr, &data)?,
+            image::Format::SRecord => image::write_srec(out, addr, &data)?,
+        }
+        Ok(())
+    }
+
+    fn replay(&self, file: &str) -> CommandResult {
+        let input = io::BufReader::new(std::fs::File::open(file)?);
+        let reader =
+            TraceReader::new(input).map_err(|err| CommandError(format!("{}: {}", file, err)))?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        // Mapping of the page of the last access, reused while the following
+        // accesses are in the same page
+        let mut page: Option<(usize, Mapping)> = None;
+        for record in reader {
+            let record = record.map_err(|err| CommandError(format!("{}: {}", file, err)))?;
+            let addr = usize::try_from(record.address).map_err(|_| {
+                CommandError(format!(
+                    "address {:#x} does not fit in the address space",
+                    record.address
+                ))
+            })?;
+            let page_addr = addr - addr % page_size;
+            let map = match page.take() {
+                Some((current, map)) if current == page_addr => map,
+                _ => self.map(page_addr, page_size)?,
+            };
+            let (_, map) = page.insert((page_addr, map));
+            for mismatch in trace::replay(map, page_addr as u64, Some(record))? {
+                println!("{} (read {:#x})", mismatch.record, mismatch.actual);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "init-table")]
+    fn batch(&self, file: &str) -> CommandResult {
+        let mut batch =
+            Batch::load(file).map_err(|err| CommandError(format!("{}: {}", file, err)))?;
+        batch.device(&self.device);
+        for read in unsafe { batch.run()? } {
+            println!(
+                "{:#x}: 0x{:0width$X}",
+                read.address,
+                read.value,
+                width = read.width * 2
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "init-table"))]
+    fn batch(&self, _file: &str) -> CommandResult {
+        Err(CommandError(
+            "init tables are not supported, rebuild with the init-table feature".to_string(),
+        ))
+    }
+}
+
+fn image_format(file: &str) -> Result<image::Format, CommandError> {
+    image::Format::from_path(file).ok_or_else(|| {
+        CommandError(format!(
+            "{}: unknown image format, expected a .hex or .srec file",
+            file
+        ))
+    })
+}
+
+/// Check the alignment of `addr` before mapping it, as the accessors of the
+/// mapping report misaligned offsets relative to the mapped address
+fn check_aligned(addr: usize, align: usize) -> CommandResult {
+    if !addr.is_multiple_of(align) {
+        return Err(CommandError(format!(
+            "address {:#x} is not aligned to {} bytes",
+            addr, align
+        )));
+    }
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    let text = text.replace('_', "");
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn parse_value(text: &str) -> u64 {
+    parse_number(text).unwrap_or_else(|| {
+        eprintln!("devmem: invalid number {:?}", text);
+        process::exit(2);
+    })
+}
+
+fn number(text: &str) -> usize {
+    match usize::try_from(parse_value(text)) {
+        Ok(number) => number,
+        Err(_) => {
+            eprintln!("devmem: {} does not fit in the address space", text);
+            process::exit(2);
+        }
+    }
+}
+
+fn width_bits(text: &str) -> usize {
+    match text {
+        "8" | "b" => 8,
+        "16" | "h" => 16,
+        "32" | "w" => 32,
+        "64" | "l" => 64,
+        _ => {
+            eprintln!("devmem: invalid width {:?}, expected 8, 16, 32 or 64", text);
+            process::exit(2);
+        }
+    }
+}
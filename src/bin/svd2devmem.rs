@@ -0,0 +1,31 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Generate devmem register maps from a CMSIS-SVD file
+//!
+//! Usage: `svd2devmem INPUT.svd [OUTPUT.rs]`
+
+use std::process;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.as_slice() {
+        [input] => std::fs::read_to_string(input)
+            .map_err(devmem::svd::Error::from)
+            .and_then(|svd| devmem::svd::generate(&svd))
+            .map(|code| print!("{}", code)),
+        [input, output] => devmem::svd::generate_file(input, output),
+        _ => {
+            eprintln!("Usage: svd2devmem INPUT.svd [OUTPUT.rs]");
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("svd2devmem: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,108 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Data cache maintenance on ranges of cached mappings
+//!
+//! Both operations end with a full barrier, so that they complete before the
+//! following accesses.
+
+use crate::{barrier, Result};
+
+/// Write the dirty cache lines covering `len` bytes at `ptr` back to memory
+pub(crate) fn flush(ptr: *const u8, len: usize) -> Result<()> {
+    arch::for_each_line(ptr, len, arch::flush_line)?;
+    barrier::full();
+    Ok(())
+}
+
+/// Discard the cache lines covering `len` bytes at `ptr`, so that the next
+/// reads fetch the data from memory
+pub(crate) fn invalidate(ptr: *const u8, len: usize) -> Result<()> {
+    arch::for_each_line(ptr, len, arch::invalidate_line)?;
+    barrier::full();
+    Ok(())
+}
+
+/// Call `op` with the address of each cache line of `line_size` bytes
+/// covering `len` bytes at `ptr`
+#[allow(dead_code)]
+fn lines(ptr: *const u8, len: usize, line_size: usize, op: fn(*const u8)) {
+    if len == 0 {
+        return;
+    }
+    let start = ptr as usize & !(line_size - 1);
+    let end = ptr as usize + len;
+    for line in (start..end).step_by(line_size) {
+        op(line as *const u8);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    use crate::Result;
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{__cpuid, _mm_clflush};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{__cpuid, _mm_clflush};
+
+    pub fn for_each_line(ptr: *const u8, len: usize, op: fn(*const u8)) -> Result<()> {
+        // CLFLUSH line size, in units of 8 bytes
+        let line_size = ((__cpuid(1).ebx >> 8) & 0xff) as usize * 8;
+        super::lines(ptr, len, line_size.max(32), op);
+        Ok(())
+    }
+
+    pub fn flush_line(line: *const u8) {
+        unsafe { _mm_clflush(line) }
+    }
+
+    /// clflush also writes back dirty lines, as x86 has no way to discard
+    /// them from user space
+    pub fn invalidate_line(line: *const u8) {
+        unsafe { _mm_clflush(line) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use crate::Result;
+    use std::arch::asm;
+
+    pub fn for_each_line(ptr: *const u8, len: usize, op: fn(*const u8)) -> Result<()> {
+        // Smallest data cache line size, as log2 of the number of words in
+        // CTR_EL0.DminLine, which Linux lets user space read
+        let ctr: u64;
+        unsafe { asm!("mrs {}, ctr_el0", out(reg) ctr, options(nomem, nostack)) };
+        let line_size = 4 << ((ctr >> 16) & 0xf);
+        super::lines(ptr, len, line_size, op);
+        Ok(())
+    }
+
+    pub fn flush_line(line: *const u8) {
+        unsafe { asm!("dc cvac, {}", in(reg) line, options(nostack, preserves_flags)) }
+    }
+
+    /// `dc ivac` is not available at EL0, so the lines are cleaned and
+    /// invalidated
+    pub fn invalidate_line(line: *const u8) {
+        unsafe { asm!("dc civac, {}", in(reg) line, options(nostack, preserves_flags)) }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+mod arch {
+    use crate::{Error, Result};
+
+    pub fn for_each_line(_ptr: *const u8, _len: usize, _op: fn(*const u8)) -> Result<()> {
+        Err(Error::Unsupported(
+            "cache maintenance is not available from user space on this architecture".to_string(),
+        ))
+    }
+
+    pub fn flush_line(_line: *const u8) {}
+
+    pub fn invalidate_line(_line: *const u8) {}
+}
@@ -0,0 +1,180 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Physically contiguous buffers for DMA
+//!
+//! A `DmaBuffer` is allocated either from huge pages, which are physically
+//! contiguous and locked in memory, or from a buffer reserved by the
+//! u-dma-buf driver. In both cases its physical address can be passed to a
+//! device, while the CPU accesses it with the same API as a `Mapping`.
+//!
+//! ```no_run
+//! use devmem::dma::DmaBuffer;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mut buffer = DmaBuffer::hugepage(64 * 1024)?;
+//! buffer.write_u32(0x0, 0xdead_beef)?;
+//! let descriptor_addr = buffer.physical_addr();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::pagemap::Pagemap;
+use crate::region::Region;
+use crate::{Error, Mapping, Result};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::ptr;
+
+/// Physically contiguous buffer with a known physical address
+pub struct DmaBuffer {
+    mapping: Mapping,
+    physical_addr: u64,
+    len: usize,
+}
+
+impl DmaBuffer {
+    /// Allocate a buffer of at least `len` bytes from huge pages
+    ///
+    /// The length is rounded up to a multiple of the default huge page size,
+    /// and the pages are locked in memory with mlock(). Huge pages must be
+    /// reserved beforehand, for example in `/proc/sys/vm/nr_hugepages`, and
+    /// translating the buffer address requires CAP_SYS_ADMIN.
+    ///
+    /// A buffer spanning more than one huge page is returned only if the
+    /// pages happen to be physically adjacent.
+    pub fn hugepage(len: usize) -> Result<DmaBuffer> {
+        if len == 0 {
+            return Err(Error::ZeroLength);
+        }
+        let huge_page_size = huge_page_size()?;
+        let len = len.checked_add(huge_page_size - 1).ok_or(Error::Overflow)? / huge_page_size
+            * huge_page_size;
+
+        let map_base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS | libc::MAP_HUGETLB | libc::MAP_POPULATE,
+                -1,
+                0,
+            )
+        };
+        if map_base == libc::MAP_FAILED {
+            return Err(Error::Mmap(io::Error::last_os_error()));
+        }
+        // From now on the pages are unmapped when the mapping is dropped
+        let mapping = unsafe { Mapping::from_raw(map_base, len) };
+        if unsafe { libc::mlock(map_base, len) } != 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        let pagemap = Pagemap::current()?;
+        let virtual_addr = map_base as usize;
+        let physical_addr = pagemap.translate(virtual_addr)?;
+        for offset in (huge_page_size..len).step_by(huge_page_size) {
+            if pagemap.translate(virtual_addr + offset)? != physical_addr + offset as u64 {
+                return Err(Error::Unsupported(format!(
+                    "the huge pages of a {} bytes buffer are not physically contiguous",
+                    len
+                )));
+            }
+        }
+
+        Ok(DmaBuffer {
+            mapping,
+            physical_addr,
+            len,
+        })
+    }
+
+    /// Map the buffer reserved by the u-dma-buf driver as `name`, like
+    /// `udmabuf0`
+    pub fn udmabuf(name: &str) -> Result<DmaBuffer> {
+        DmaBuffer::udmabuf_in("/sys", "/dev", name)
+    }
+
+    /// Map the u-dma-buf buffer `name` with the sysfs tree mounted at
+    /// `sysfs_root` and the device files in `dev_root`
+    ///
+    /// The attributes are looked up in the `u-dma-buf` class, and in the
+    /// `udmabuf` class of older versions of the driver.
+    pub fn udmabuf_in<P: AsRef<Path>, Q: AsRef<Path>>(
+        sysfs_root: P,
+        dev_root: Q,
+        name: &str,
+    ) -> Result<DmaBuffer> {
+        let class_path = ["class/u-dma-buf", "class/udmabuf"]
+            .iter()
+            .map(|class| sysfs_root.as_ref().join(class).join(name))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no u-dma-buf buffer named {}", name),
+                ))
+            })?;
+        let physical_addr = read_number_attribute(&class_path.join("phys_addr"))?;
+        let len = usize::try_from(read_number_attribute(&class_path.join("size"))?)
+            .map_err(|_| Error::Overflow)?;
+
+        // Opening the device with O_SYNC, as the builder does, makes the
+        // driver map the buffer uncached unless configured otherwise
+        let mapping = unsafe {
+            Mapping::builder()
+                .device(dev_root.as_ref().join(name))
+                .len(len)
+                .map()?
+        };
+        Ok(DmaBuffer {
+            mapping,
+            physical_addr,
+            len,
+        })
+    }
+
+    /// Physical address of the start of the buffer
+    pub fn physical_addr(&self) -> u64 {
+        self.physical_addr
+    }
+
+    /// Size of the buffer in bytes
+    pub fn size(&self) -> usize {
+        self.len
+    }
+
+    impl_read_api!();
+    impl_write_api!();
+
+    fn region(&self) -> Region {
+        self.mapping.region()
+    }
+}
+
+/// Default huge page size, from `/proc/meminfo`
+fn huge_page_size() -> Result<usize> {
+    let meminfo = fs::read_to_string("/proc/meminfo").map_err(Error::Io)?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Hugepagesize:"))
+        .and_then(|size| size.trim().strip_suffix("kB"))
+        .and_then(|size| size.trim().parse::<usize>().ok())
+        .map(|size| size * 1024)
+        .ok_or_else(|| Error::Unsupported("huge pages are not available".to_string()))
+}
+
+fn read_number_attribute(path: &Path) -> Result<u64> {
+    let text = fs::read_to_string(path).map_err(Error::Io)?;
+    let text = text.trim();
+    let value = match text.strip_prefix("0x") {
+        Some(digits) => u64::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+    value.map_err(|_| Error::Parse(format!("invalid value {:?} in {}", text, path.display())))
+}
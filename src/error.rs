@@ -0,0 +1,114 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::iomem::Issue;
+use std::fmt;
+use std::io;
+
+/// Errors returned when creating or accessing a `Mapping`
+#[derive(Debug)]
+pub enum Error {
+    /// The device could not be opened
+    Open(io::Error),
+    /// mmap() failed with the contained OS error
+    Mmap(io::Error),
+    /// The kernel refused to map the range of /dev/mem, usually because it
+    /// is built with CONFIG_STRICT_DEVMEM
+    StrictDevmem,
+    /// The requested range does not fit in the address space
+    Overflow,
+    /// The requested range is empty
+    ZeroLength,
+    /// The access at `offset` is not aligned to `align` bytes
+    Misaligned { offset: usize, align: usize },
+    /// The access of `len` bytes at `offset` falls outside the mapping
+    OutOfBounds { offset: usize, len: usize },
+    /// An I/O error not related to opening or mapping the device
+    Io(io::Error),
+    /// A system file or table could not be parsed, or its content is
+    /// inconsistent
+    Parse(String),
+    /// The range was refused after checking it against `/proc/iomem`
+    Iomem(Issue),
+    /// The operation is not supported by the device or the platform
+    Unsupported(String),
+    /// The virtual page is not present in RAM, because it was never touched
+    /// or it is swapped out
+    PageNotPresent { virtual_addr: usize, swapped: bool },
+    /// The page frame numbers are hidden because the process does not have
+    /// CAP_SYS_ADMIN
+    PfnHidden,
+    /// Polling a register timed out, with `last` as the last value read
+    Timeout { last: u64 },
+}
+
+/// Result type of the operations on a `Mapping`
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Open(err) => write!(f, "failed to open device: {}", err),
+            Error::Mmap(err) => write!(f, "mmap failed: {}", err),
+            Error::StrictDevmem => write!(
+                f,
+                "access to /dev/mem denied (kernel built with CONFIG_STRICT_DEVMEM?)"
+            ),
+            Error::Overflow => write!(f, "range overflows the address space"),
+            Error::ZeroLength => write!(f, "the length must be greater than 0"),
+            Error::Misaligned { offset, align } => {
+                write!(f, "offset {:#x} is not aligned to {} bytes", offset, align)
+            }
+            Error::OutOfBounds { offset, len } => write!(
+                f,
+                "access of {} bytes at offset {:#x} is out of bounds",
+                len, offset
+            ),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Parse(msg) => write!(f, "parse error: {}", msg),
+            Error::Iomem(issue) => write!(f, "refused by /proc/iomem check: {}", issue),
+            Error::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            Error::PageNotPresent {
+                virtual_addr,
+                swapped,
+            } => write!(
+                f,
+                "page at {:#x} is not present{}",
+                virtual_addr,
+                if *swapped { " (swapped out)" } else { "" }
+            ),
+            Error::PfnHidden => write!(
+                f,
+                "page frame numbers are hidden (CAP_SYS_ADMIN is required)"
+            ),
+            Error::Timeout { last } => {
+                write!(f, "polling timed out, last value read {:#x}", last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Open(err) | Error::Mmap(err) | Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Open(err) | Error::Mmap(err) | Error::Io(err) => err,
+            Error::StrictDevmem | Error::PfnHidden => {
+                io::Error::new(io::ErrorKind::PermissionDenied, err)
+            }
+            Error::Timeout { .. } => io::Error::new(io::ErrorKind::TimedOut, err),
+            _ => io::Error::new(io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
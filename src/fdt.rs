@@ -0,0 +1,453 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Flattened device tree lookup of peripheral base addresses
+//!
+//! The device tree blob passed by the bootloader is exposed by the kernel
+//! at `/sys/firmware/fdt`. Nodes can be found by path, label or
+//! `compatible` string, and their `reg` property is translated through the
+//! `ranges` of the parent buses into physical addresses, which can be
+//! mapped directly.
+//!
+//! ```no_run
+//! use devmem::fdt::DeviceTree;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let tree = DeviceTree::load()?;
+//! let uart = tree.find_by_label("uart0").expect("no uart0 label");
+//! let mapping = unsafe { uart.map(0)? };
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, Mapping, Result};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// Property of a device tree node
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub value: Vec<u8>,
+}
+
+/// Region of a `reg` property, translated to a physical address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reg {
+    pub address: u64,
+    pub size: u64,
+}
+
+struct NodeData {
+    name: String,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    properties: Vec<Property>,
+}
+
+/// Parsed flattened device tree
+pub struct DeviceTree {
+    nodes: Vec<NodeData>,
+}
+
+impl DeviceTree {
+    /// Load the device tree blob of the running system from
+    /// `/sys/firmware/fdt`
+    pub fn load() -> Result<DeviceTree> {
+        DeviceTree::load_from("/sys/firmware/fdt")
+    }
+
+    /// Load a device tree blob from a file
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<DeviceTree> {
+        let blob = fs::read(path).map_err(Error::Io)?;
+        DeviceTree::parse(&blob)
+    }
+
+    /// Parse a flattened device tree blob
+    pub fn parse(blob: &[u8]) -> Result<DeviceTree> {
+        let header =
+            |index: usize| be_u32(blob, index * 4).ok_or_else(|| error("truncated header"));
+        if header(0)? != FDT_MAGIC {
+            return Err(error("invalid magic number"));
+        }
+        let total_size = header(1)? as usize;
+        let struct_offset = header(2)? as usize;
+        let strings_offset = header(3)? as usize;
+        let strings_size = header(8)? as usize;
+        if total_size > blob.len() {
+            return Err(error("truncated blob"));
+        }
+        let strings = strings_offset
+            .checked_add(strings_size)
+            .and_then(|end| blob.get(strings_offset..end))
+            .ok_or_else(|| error("strings block out of bounds"))?;
+
+        let mut nodes: Vec<NodeData> = Vec::new();
+        let mut current: Option<usize> = None;
+        let mut offset = struct_offset;
+        loop {
+            let token = be_u32(blob, offset).ok_or_else(|| error("truncated structure block"))?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_string(blob, offset).ok_or_else(|| error("invalid node name"))?;
+                    offset = align4(offset + name.len() + 1);
+                    let index = nodes.len();
+                    nodes.push(NodeData {
+                        name: name.to_string(),
+                        parent: current,
+                        children: Vec::new(),
+                        properties: Vec::new(),
+                    });
+                    if let Some(parent) = current {
+                        nodes[parent].children.push(index);
+                    } else if index != 0 {
+                        return Err(error("multiple root nodes"));
+                    }
+                    current = Some(index);
+                }
+                FDT_END_NODE => {
+                    let node = current.ok_or_else(|| error("unbalanced end of node"))?;
+                    current = nodes[node].parent;
+                }
+                FDT_PROP => {
+                    let node = current.ok_or_else(|| error("property outside of a node"))?;
+                    let len = be_u32(blob, offset).ok_or_else(|| error("truncated property"))?;
+                    let name_offset =
+                        be_u32(blob, offset + 4).ok_or_else(|| error("truncated property"))?;
+                    let value = blob
+                        .get(offset + 8..offset + 8 + len as usize)
+                        .ok_or_else(|| error("property value out of bounds"))?;
+                    let name = c_string(strings, name_offset as usize)
+                        .ok_or_else(|| error("invalid property name"))?;
+                    nodes[node].properties.push(Property {
+                        name: name.to_string(),
+                        value: value.to_vec(),
+                    });
+                    offset = align4(offset + 8 + len as usize);
+                }
+                FDT_NOP => {}
+                FDT_END => break,
+                _ => return Err(error("unknown structure token")),
+            }
+        }
+
+        if nodes.is_empty() || current.is_some() {
+            return Err(error("unbalanced structure block"));
+        }
+        Ok(DeviceTree { nodes })
+    }
+
+    /// Root node of the tree
+    pub fn root(&self) -> Node<'_> {
+        self.node(0)
+    }
+
+    /// Find a node by its path, like `/soc/serial@fe201000`
+    ///
+    /// The unit address can be omitted from a path component when only one
+    /// child has that name.
+    pub fn find_by_path(&self, path: &str) -> Option<Node<'_>> {
+        let mut node = self.root();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let mut matches = node.children().filter(|child| {
+                child.name() == component || child.name().split('@').next() == Some(component)
+            });
+            let exact = node.children().find(|child| child.name() == component);
+            node = match exact {
+                Some(child) => child,
+                None => {
+                    let child = matches.next()?;
+                    if matches.next().is_some() {
+                        return None;
+                    }
+                    child
+                }
+            };
+        }
+        Some(node)
+    }
+
+    /// Find a node by its label, using the `__symbols__` node, or by an alias
+    /// in the `aliases` node
+    pub fn find_by_label(&self, label: &str) -> Option<Node<'_>> {
+        ["/__symbols__", "/aliases"]
+            .iter()
+            .filter_map(|path| self.find_by_path(path))
+            .find_map(|node| node.property_str(label))
+            .and_then(|path| self.find_by_path(path))
+    }
+
+    /// Find the first node whose `compatible` property lists `compatible`
+    pub fn find_compatible(&self, compatible: &str) -> Option<Node<'_>> {
+        self.find_all_compatible(compatible).into_iter().next()
+    }
+
+    /// Find all the nodes whose `compatible` property lists `compatible`
+    pub fn find_all_compatible(&self, compatible: &str) -> Vec<Node<'_>> {
+        (0..self.nodes.len())
+            .map(|index| self.node(index))
+            .filter(|node| node.compatible().contains(&compatible))
+            .collect()
+    }
+
+    fn node(&self, index: usize) -> Node<'_> {
+        Node { tree: self, index }
+    }
+}
+
+/// Node of a `DeviceTree`
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    tree: &'a DeviceTree,
+    index: usize,
+}
+
+impl<'a> Node<'a> {
+    /// Name of the node, including the unit address
+    pub fn name(&self) -> &'a str {
+        &self.data().name
+    }
+
+    /// Full path of the node
+    pub fn path(&self) -> String {
+        let mut components = Vec::new();
+        let mut node = Some(*self);
+        while let Some(n) = node {
+            components.push(n.name());
+            node = n.parent();
+        }
+        components.pop();
+        components.reverse();
+        format!("/{}", components.join("/"))
+    }
+
+    /// Parent node, or `None` for the root
+    pub fn parent(&self) -> Option<Node<'a>> {
+        self.data().parent.map(|index| self.tree.node(index))
+    }
+
+    /// Child nodes
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let tree = self.tree;
+        tree.nodes[self.index]
+            .children
+            .iter()
+            .map(move |&index| tree.node(index))
+    }
+
+    /// Properties of the node
+    pub fn properties(&self) -> &'a [Property] {
+        &self.data().properties
+    }
+
+    /// Raw value of a property
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.value.as_slice())
+    }
+
+    /// Value of a property holding a single string
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        let value = self.property(name)?;
+        std::str::from_utf8(value.strip_suffix(&[0]).unwrap_or(value)).ok()
+    }
+
+    /// Strings of the `compatible` property
+    pub fn compatible(&self) -> Vec<&'a str> {
+        self.property("compatible")
+            .map(|value| {
+                value
+                    .split(|&b| b == 0)
+                    .filter(|s| !s.is_empty())
+                    .filter_map(|s| std::str::from_utf8(s).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Check whether the node is enabled, according to its `status`
+    pub fn is_enabled(&self) -> bool {
+        matches!(
+            self.property_str("status"),
+            None | Some("okay") | Some("ok")
+        )
+    }
+
+    /// Regions of the `reg` property, translated to physical addresses
+    /// through the `ranges` of the parent buses
+    pub fn reg(&self) -> Result<Vec<Reg>> {
+        let parent = self
+            .parent()
+            .ok_or_else(|| error("the root node has no reg property"))?;
+        let value = self
+            .property("reg")
+            .ok_or_else(|| error(&format!("{} has no reg property", self.path())))?;
+        let address_cells = parent.address_cells();
+        let size_cells = parent.size_cells();
+        let cells = read_cells(value)?;
+        let entry_cells = address_cells + size_cells;
+        if entry_cells == 0 || !cells.len().is_multiple_of(entry_cells) {
+            return Err(error(&format!("malformed reg property in {}", self.path())));
+        }
+
+        cells
+            .chunks(entry_cells)
+            .map(|entry| {
+                let address = cells_value(&entry[..address_cells])?;
+                let size = cells_value(&entry[address_cells..])?;
+                let address = parent.translate(address)?;
+                let address = u64::try_from(address)
+                    .map_err(|_| error("translated address does not fit in 64 bits"))?;
+                let size =
+                    u64::try_from(size).map_err(|_| error("size does not fit in 64 bits"))?;
+                Ok(Reg { address, size })
+            })
+            .collect()
+    }
+
+    /// Map the region at `index` of the `reg` property
+    ///
+    /// Fails with `Error::ZeroLength` for regions without a size, like the
+    /// `reg` of nodes on a bus with `#size-cells = <0>`.
+    ///
+    /// # Safety
+    ///
+    /// See `Mapping::new`.
+    pub unsafe fn map(&self, index: usize) -> Result<Mapping> {
+        let reg = self
+            .reg()?
+            .get(index)
+            .copied()
+            .ok_or_else(|| error(&format!("{} has no reg entry {}", self.path(), index)))?;
+        if reg.size == 0 {
+            return Err(Error::ZeroLength);
+        }
+        let address = usize::try_from(reg.address).map_err(|_| Error::Overflow)?;
+        let size = usize::try_from(reg.size).map_err(|_| Error::Overflow)?;
+        Mapping::new(address, size)
+    }
+
+    /// `#address-cells` of the children of this node
+    fn address_cells(&self) -> usize {
+        self.cells_property("#address-cells").unwrap_or(2)
+    }
+
+    /// `#size-cells` of the children of this node
+    fn size_cells(&self) -> usize {
+        self.cells_property("#size-cells").unwrap_or(1)
+    }
+
+    fn cells_property(&self, name: &str) -> Option<usize> {
+        self.property(name)
+            .and_then(|value| be_u32(value, 0))
+            .map(|cells| cells as usize)
+    }
+
+    /// Translate an address of the bus of this node to a physical address
+    fn translate(&self, mut address: u128) -> Result<u128> {
+        let mut bus = *self;
+        while let Some(parent) = bus.parent() {
+            let ranges = bus.property("ranges").ok_or_else(|| {
+                error(&format!(
+                    "{} has no ranges property, its addresses cannot be translated",
+                    bus.path()
+                ))
+            })?;
+
+            // An empty ranges property is an identity mapping
+            if !ranges.is_empty() {
+                let child_cells = bus.address_cells();
+                let parent_cells = parent.address_cells();
+                let size_cells = bus.size_cells();
+                let entry_cells = child_cells + parent_cells + size_cells;
+                let cells = read_cells(ranges)?;
+                if entry_cells == 0 || !cells.len().is_multiple_of(entry_cells) {
+                    return Err(error(&format!(
+                        "malformed ranges property in {}",
+                        bus.path()
+                    )));
+                }
+
+                address = cells
+                    .chunks(entry_cells)
+                    .find_map(|entry| {
+                        let child = cells_value(&entry[..child_cells]).ok()?;
+                        let parent =
+                            cells_value(&entry[child_cells..child_cells + parent_cells]).ok()?;
+                        let size = cells_value(&entry[child_cells + parent_cells..]).ok()?;
+                        if address >= child && address - child < size {
+                            Some(parent + (address - child))
+                        } else {
+                            None
+                        }
+                    })
+                    .ok_or_else(|| {
+                        error(&format!(
+                            "address {:#x} is not in the ranges of {}",
+                            address,
+                            bus.path()
+                        ))
+                    })?;
+            }
+            bus = parent;
+        }
+        Ok(address)
+    }
+
+    fn data(&self) -> &'a NodeData {
+        &self.tree.nodes[self.index]
+    }
+}
+
+fn error(message: &str) -> Error {
+    Error::Parse(format!("device tree: {}", message))
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn c_string(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let end = bytes.iter().position(|&b| b == 0)?;
+    std::str::from_utf8(&bytes[..end]).ok()
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn read_cells(value: &[u8]) -> Result<Vec<u32>> {
+    if !value.len().is_multiple_of(4) {
+        return Err(error("property length is not a multiple of a cell"));
+    }
+    Ok((0..value.len())
+        .step_by(4)
+        .filter_map(|offset| be_u32(value, offset))
+        .collect())
+}
+
+/// Combine big-endian cells into a single value
+fn cells_value(cells: &[u32]) -> Result<u128> {
+    if cells.len() > 4 {
+        return Err(error("values of more than 4 cells are not supported"));
+    }
+    Ok(cells
+        .iter()
+        .fold(0u128, |value, &cell| value << 32 | u128::from(cell)))
+}
@@ -0,0 +1,381 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Comparison of access sequences with golden files
+//!
+//! A golden file lists the expected accesses, one per line, in the format
+//! printed by `sim::Access`:
+//!
+//! ```text
+//! # Reset the controller, then configure the clocks in any order
+//! write 0x0 u32 0x00000001
+//! read 0x4 u32 *
+//! {
+//! write 0x10 u32 0x0000xx03
+//! write 0x14 u32 0x00000100
+//! }
+//! ```
+//!
+//! A value of `*` matches any value, and an `x` in place of a hexadecimal
+//! digit matches any digit. The accesses between `{` and `}` may happen in
+//! any order. Text after `#` is a comment.
+//!
+//! ```
+//! use devmem::golden::Golden;
+//! use devmem::sim::SimMemory;
+//! use devmem::MemoryWrite;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mut memory = SimMemory::new();
+//! memory.add_region(0x0, 0x100);
+//! memory.write_u32(0x14, 0x100)?;
+//! memory.write_u32(0x10, 0x1203)?;
+//!
+//! let golden = Golden::parse("{\nwrite 0x10 u32 0x0000xx03\nwrite 0x14 u32 0x00000100\n}")?;
+//! golden.assert_matches(&memory.accesses());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sim::{Access, AccessKind};
+use crate::{Error, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Expected access of a golden file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Expectation {
+    kind: AccessKind,
+    offset: usize,
+    /// Width of the access in bytes
+    width: usize,
+    /// Bits of the value that are compared
+    mask: u64,
+    value: u64,
+}
+
+impl Expectation {
+    fn matches(&self, access: &Access) -> bool {
+        access.kind == self.kind
+            && access.offset == self.offset
+            && access.width == self.width
+            && access.value & self.mask == self.value
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        };
+        write!(f, "{} {:#x} u{} ", kind, self.offset, self.width * 8)?;
+        if self.mask == 0 {
+            return f.write_str("*");
+        }
+        f.write_str("0x")?;
+        for digit in (0..self.width * 2).rev() {
+            let shift = digit * 4;
+            if (self.mask >> shift) & 0xf == 0 {
+                f.write_str("x")?;
+            } else {
+                write!(f, "{:x}", (self.value >> shift) & 0xf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    Single(Expectation),
+    /// Accesses that may happen in any order
+    Unordered(Vec<Expectation>),
+}
+
+/// First difference between a sequence of accesses and a golden file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// Index of the access in the sequence
+    pub index: usize,
+    /// Expected access, or `None` if the sequence has more accesses than the
+    /// golden file
+    pub expected: Option<String>,
+    /// Actual access, or `None` if the sequence ended too early
+    pub actual: Option<Access>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "access {}: ", self.index)?;
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, "expected {}, got {}", expected, actual)
+            }
+            (Some(expected), None) => write!(f, "expected {}, got no access", expected),
+            (None, Some(actual)) => write!(f, "unexpected {}", actual),
+            (None, None) => write!(f, "no difference"),
+        }
+    }
+}
+
+/// Expected sequence of accesses
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Golden {
+    entries: Vec<Entry>,
+}
+
+impl Golden {
+    /// Load a golden file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Golden> {
+        let text = fs::read_to_string(path).map_err(Error::Io)?;
+        Golden::parse(&text)
+    }
+
+    /// Parse the content of a golden file
+    pub fn parse(text: &str) -> Result<Golden> {
+        let mut entries = Vec::new();
+        // Expectations of the unordered group being parsed, if any
+        let mut group: Option<Vec<Expectation>> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let error =
+                |message: &str| Error::Parse(format!("golden line {}: {}", index + 1, message));
+            let line = line.split('#').next().unwrap_or("").trim();
+            match line {
+                "" => {}
+                "{" => {
+                    if group.is_some() {
+                        return Err(error("unordered groups cannot be nested"));
+                    }
+                    group = Some(Vec::new());
+                }
+                "}" => {
+                    let expectations = group
+                        .take()
+                        .ok_or_else(|| error("'}' without a matching '{'"))?;
+                    entries.push(Entry::Unordered(expectations));
+                }
+                _ => {
+                    let expectation = parse_expectation(line).map_err(|m| error(&m))?;
+                    match &mut group {
+                        Some(expectations) => expectations.push(expectation),
+                        None => entries.push(Entry::Single(expectation)),
+                    }
+                }
+            }
+        }
+        if group.is_some() {
+            return Err(Error::Parse(
+                "golden: unterminated unordered group".to_string(),
+            ));
+        }
+
+        Ok(Golden { entries })
+    }
+
+    /// Compare `accesses` with the golden sequence and return the first
+    /// difference
+    ///
+    /// The accesses of an unordered group are the next accesses, as many as
+    /// the expectations of the group, matched one-to-one with them, so that
+    /// a wildcard does not take an access needed by a more specific
+    /// expectation.
+    pub fn compare(&self, accesses: &[Access]) -> Option<Mismatch> {
+        let mut index = 0;
+        for entry in &self.entries {
+            match entry {
+                Entry::Single(expectation) => {
+                    let actual = accesses.get(index);
+                    if !actual.is_some_and(|access| expectation.matches(access)) {
+                        return Some(Mismatch {
+                            index,
+                            expected: Some(expectation.to_string()),
+                            actual: actual.copied(),
+                        });
+                    }
+                    index += 1;
+                }
+                Entry::Unordered(expectations) => {
+                    let end = accesses.len().min(index + expectations.len());
+                    let group = &accesses[index..end];
+                    let matched = match_unordered(expectations, group);
+                    let mut access_matched = vec![false; group.len()];
+                    for &access in matched.iter().flatten() {
+                        access_matched[access] = true;
+                    }
+                    // First access not matched, or the end of the accesses if
+                    // there are too few of them
+                    let first = access_matched
+                        .iter()
+                        .position(|&matched| !matched)
+                        .unwrap_or(group.len());
+                    if first < expectations.len() {
+                        let expected = expectations
+                            .iter()
+                            .zip(&matched)
+                            .filter(|(_, access)| access.is_none())
+                            .map(|(expectation, _)| expectation.to_string())
+                            .collect::<Vec<_>>()
+                            .join(" or ");
+                        return Some(Mismatch {
+                            index: index + first,
+                            expected: Some(expected),
+                            actual: group.get(first).copied(),
+                        });
+                    }
+                    index = end;
+                }
+            }
+        }
+        accesses.get(index).map(|access| Mismatch {
+            index,
+            expected: None,
+            actual: Some(*access),
+        })
+    }
+
+    /// Panic with the first difference if `accesses` do not match the golden
+    /// sequence
+    pub fn assert_matches(&self, accesses: &[Access]) {
+        if let Some(mismatch) = self.compare(accesses) {
+            panic!("access sequence differs from the golden file: {}", mismatch);
+        }
+    }
+}
+
+/// Match each expectation of an unordered group with a distinct access
+/// among `accesses`, and return the index of the access matched by each
+/// expectation
+///
+/// Finds a maximum bipartite matching with augmenting paths, which is fast
+/// enough for groups of a few dozen accesses.
+fn match_unordered(expectations: &[Expectation], accesses: &[Access]) -> Vec<Option<usize>> {
+    // Expectation matched by each access
+    let mut matched_by: Vec<Option<usize>> = vec![None; accesses.len()];
+    // Match the most specific expectations first, so that the wildcards are
+    // the ones reported when some accesses are missing
+    let mut order: Vec<usize> = (0..expectations.len()).collect();
+    order
+        .sort_by_key(|&expectation| std::cmp::Reverse(expectations[expectation].mask.count_ones()));
+    for expectation in order {
+        let mut visited = vec![false; accesses.len()];
+        augment(
+            expectations,
+            accesses,
+            expectation,
+            &mut visited,
+            &mut matched_by,
+        );
+    }
+
+    let mut matched = vec![None; expectations.len()];
+    for (access, expectation) in matched_by.iter().enumerate() {
+        if let Some(expectation) = expectation {
+            matched[*expectation] = Some(access);
+        }
+    }
+    matched
+}
+
+/// Look for an access for `expectation`, taking it from the expectation
+/// that matched it if that one can be matched with another access
+fn augment(
+    expectations: &[Expectation],
+    accesses: &[Access],
+    expectation: usize,
+    visited: &mut [bool],
+    matched_by: &mut [Option<usize>],
+) -> bool {
+    for (access, actual) in accesses.iter().enumerate() {
+        if visited[access] || !expectations[expectation].matches(actual) {
+            continue;
+        }
+        visited[access] = true;
+        let free = match matched_by[access] {
+            None => true,
+            Some(other) => augment(expectations, accesses, other, visited, matched_by),
+        };
+        if free {
+            matched_by[access] = Some(expectation);
+            return true;
+        }
+    }
+    false
+}
+
+/// Format `accesses` as a golden file, to be reviewed and edited to add
+/// wildcards and unordered groups
+pub fn format(accesses: &[Access]) -> String {
+    accesses
+        .iter()
+        .map(|access| format!("{}\n", access))
+        .collect()
+}
+
+fn parse_expectation(line: &str) -> std::result::Result<Expectation, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (kind, offset, width, value) = match fields.as_slice() {
+        [kind, offset, width, value] => (*kind, *offset, *width, *value),
+        _ => return Err("expected 'read|write OFFSET WIDTH VALUE'".to_string()),
+    };
+
+    let kind = match kind {
+        "read" => AccessKind::Read,
+        "write" => AccessKind::Write,
+        _ => return Err(format!("invalid access kind {:?}", kind)),
+    };
+    let offset = match offset.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => offset.parse(),
+    }
+    .map_err(|_| format!("invalid offset {:?}", offset))?;
+    let width = match width {
+        "u8" => 1,
+        "u16" => 2,
+        "u32" => 4,
+        "u64" => 8,
+        _ => return Err(format!("invalid width {:?}", width)),
+    };
+    let (mask, value) =
+        parse_value(value, width).ok_or_else(|| format!("invalid value {:?}", value))?;
+
+    Ok(Expectation {
+        kind,
+        offset,
+        width,
+        mask,
+        value,
+    })
+}
+
+/// Parse a value, with `*` or `x` digits as wildcards, into a mask of the
+/// compared bits and the expected value
+fn parse_value(text: &str, width: usize) -> Option<(u64, u64)> {
+    if text == "*" {
+        return Some((0, 0));
+    }
+    let digits = text.strip_prefix("0x")?;
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    let mut mask = 0u64;
+    let mut value = 0u64;
+    for digit in digits.chars() {
+        mask <<= 4;
+        value <<= 4;
+        if digit != 'x' && digit != 'X' {
+            mask |= 0xf;
+            value |= u64::from(digit.to_digit(16)?);
+        }
+    }
+    let width_mask = u64::MAX >> (64 - width * 8);
+    if value & !width_mask != 0 {
+        return None;
+    }
+    Some((mask & width_mask, value))
+}
@@ -0,0 +1,361 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Intel HEX and Motorola S-record images
+//!
+//! Images are parsed into `Segment`s of contiguous bytes, which can be
+//! written to the physical address space with `load`, one mapping per
+//! segment. A physical range can be dumped back in either format with
+//! `dump`.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Image file format
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Intel HEX
+    IntelHex,
+    /// Motorola S-record
+    SRecord,
+}
+
+impl Format {
+    /// Guess the format of an image from the extension of its path
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "hex" | "ihex" | "ihx" => Some(Format::IntelHex),
+            "srec" | "s19" | "s28" | "s37" | "mot" | "sx" => Some(Format::SRecord),
+            _ => None,
+        }
+    }
+}
+
+/// Contiguous bytes starting at a physical address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub address: usize,
+    pub data: Vec<u8>,
+}
+
+/// Error in an image, at a 1-based line number
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parse an image in the given format
+pub fn parse(format: Format, text: &str) -> Result<Vec<Segment>, ParseError> {
+    match format {
+        Format::IntelHex => parse_ihex(text),
+        Format::SRecord => parse_srec(text),
+    }
+}
+
+/// Parse an Intel HEX image
+pub fn parse_ihex(text: &str) -> Result<Vec<Segment>, ParseError> {
+    let mut segments = Vec::new();
+    let mut base = 0usize;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: &str| ParseError {
+            line: line_number,
+            message: message.to_string(),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("missing ':' at the start of the record"))?;
+        let bytes = decode_hex(record).ok_or_else(|| error("invalid hexadecimal digits"))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(error("record length does not match the byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+
+        let offset = usize::from(bytes[1]) << 8 | usize::from(bytes[2]);
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => {
+                let address = base
+                    .checked_add(offset)
+                    .ok_or_else(|| error("address overflows the address space"))?;
+                push_data(&mut segments, address, data);
+            }
+            0x01 => break,
+            0x02 if data.len() == 2 => {
+                base = (usize::from(data[0]) << 8 | usize::from(data[1])) << 4;
+            }
+            0x04 if data.len() == 2 => {
+                let upper = u64::from(data[0]) << 8 | u64::from(data[1]);
+                base = to_address(upper << 16)
+                    .ok_or_else(|| error("extended linear address overflows the address space"))?;
+            }
+            // Start addresses are not relevant when loading memory
+            0x03 | 0x05 => {}
+            0x02 | 0x04 => return Err(error("invalid extended address record")),
+            _ => return Err(error("unknown record type")),
+        }
+    }
+
+    Ok(merge_segments(segments))
+}
+
+/// Parse a Motorola S-record image
+pub fn parse_srec(text: &str) -> Result<Vec<Segment>, ParseError> {
+    let mut segments = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: &str| ParseError {
+            line: line_number,
+            message: message.to_string(),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix('S')
+            .or_else(|| line.strip_prefix('s'))
+            .ok_or_else(|| error("missing 'S' at the start of the record"))?;
+        let mut chars = record.chars();
+        let kind = chars
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .ok_or_else(|| error("invalid record type"))?;
+        let bytes =
+            decode_hex(chars.as_str()).ok_or_else(|| error("invalid hexadecimal digits"))?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(error("record length does not match the byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0xff {
+            return Err(error("checksum mismatch"));
+        }
+
+        let address_len = match kind {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            3 | 7 => 4,
+            _ => return Err(error("unknown record type")),
+        };
+        if bytes.len() < address_len + 2 {
+            return Err(error("record too short for its address"));
+        }
+        let address = bytes[1..=address_len]
+            .iter()
+            .fold(0u64, |address, b| address << 8 | u64::from(*b));
+        let data = &bytes[address_len + 1..bytes.len() - 1];
+        // Headers, record counts and start addresses are not relevant when
+        // loading memory
+        if let 1..=3 = kind {
+            let address =
+                to_address(address).ok_or_else(|| error("address overflows the address space"))?;
+            push_data(&mut segments, address, data);
+        }
+    }
+
+    Ok(merge_segments(segments))
+}
+
+/// Write `data`, starting at `address`, as an Intel HEX image
+pub fn write_ihex<W: Write>(mut out: W, address: usize, data: &[u8]) -> io::Result<()> {
+    let end = address as u64 + data.len() as u64;
+    if end > 1 << 32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Intel HEX images cannot address more than 4 GiB",
+        ));
+    }
+
+    let mut upper = 0;
+    let mut address = address as u64;
+    let mut data = data;
+    while !data.is_empty() {
+        if address >> 16 != upper {
+            upper = address >> 16;
+            write_ihex_record(&mut out, 0, 0x04, &[(upper >> 8) as u8, upper as u8])?;
+        }
+        // Records cannot cross a 64 KiB boundary
+        let to_boundary = 0x1_0000 - (address & 0xffff) as usize;
+        let len = data.len().min(16).min(to_boundary);
+        write_ihex_record(&mut out, address as u16, 0x00, &data[..len])?;
+        address += len as u64;
+        data = &data[len..];
+    }
+    write_ihex_record(&mut out, 0, 0x01, &[])
+}
+
+/// Write `data`, starting at `address`, as a Motorola S-record image
+///
+/// The smallest address size able to represent the whole range is used.
+pub fn write_srec<W: Write>(mut out: W, address: usize, data: &[u8]) -> io::Result<()> {
+    let end = address as u64 + data.len() as u64;
+    let (data_kind, end_kind, address_len) = if end <= 1 << 16 {
+        (1, 9, 2)
+    } else if end <= 1 << 24 {
+        (2, 8, 3)
+    } else if end <= 1 << 32 {
+        (3, 7, 4)
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "S-record images cannot address more than 4 GiB",
+        ));
+    };
+
+    write_srec_record(&mut out, 0, 0, 2, &[])?;
+    let mut count = 0u64;
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let chunk_address = address as u64 + 16 * i as u64;
+        write_srec_record(&mut out, data_kind, chunk_address, address_len, chunk)?;
+        count += 1;
+    }
+    if count <= 0xffff {
+        write_srec_record(&mut out, 5, count, 2, &[])?;
+    }
+    write_srec_record(&mut out, end_kind, 0, address_len, &[])
+}
+
+/// Write the segments of an image to the physical address space
+///
+/// # Safety
+///
+/// See `Mapping::new`.
+pub unsafe fn load(segments: &[Segment]) -> crate::Result<()> {
+    for segment in segments.iter().filter(|s| !s.data.is_empty()) {
+        crate::write_from_slice(segment.address, &segment.data)?;
+    }
+    Ok(())
+}
+
+/// Dump `len` bytes of the physical address space, starting at
+/// `physical_addr`, as an image in the given format
+///
+/// # Safety
+///
+/// See `Mapping::new`.
+pub unsafe fn dump<W: Write>(
+    out: W,
+    format: Format,
+    physical_addr: usize,
+    len: usize,
+) -> crate::Result<()> {
+    let mut data = vec![0; len];
+    crate::read_into_slice(physical_addr, &mut data)?;
+    let result = match format {
+        Format::IntelHex => write_ihex(out, physical_addr, &data),
+        Format::SRecord => write_srec(out, physical_addr, &data),
+    };
+    result.map_err(crate::Error::Io)
+}
+
+fn write_ihex_record<W: Write>(out: &mut W, offset: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut bytes = vec![data.len() as u8, (offset >> 8) as u8, offset as u8, kind];
+    bytes.extend_from_slice(data);
+    let checksum = bytes
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_add(*b))
+        .wrapping_neg();
+    bytes.push(checksum);
+    writeln!(out, ":{}", encode_hex(&bytes))
+}
+
+fn write_srec_record<W: Write>(
+    out: &mut W,
+    kind: u8,
+    address: u64,
+    address_len: usize,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut bytes = vec![(address_len + data.len() + 1) as u8];
+    bytes.extend((0..address_len).rev().map(|i| (address >> (8 * i)) as u8));
+    bytes.extend_from_slice(data);
+    let checksum = !bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    bytes.push(checksum);
+    writeln!(out, "S{}{}", kind, encode_hex(&bytes))
+}
+
+/// Append data to the last segment if it is contiguous, or start a new one
+fn push_data(segments: &mut Vec<Segment>, address: usize, data: &[u8]) {
+    if data.is_empty() {
+        return;
+    }
+    if let Some(last) = segments.last_mut() {
+        if last.address.checked_add(last.data.len()) == Some(address) {
+            last.data.extend_from_slice(data);
+            return;
+        }
+    }
+    segments.push(Segment {
+        address,
+        data: data.to_vec(),
+    });
+}
+
+/// Sort the segments by address and merge the contiguous ones
+///
+/// Where segments overlap, the bytes of the segment starting at the higher
+/// address are kept.
+fn merge_segments(mut segments: Vec<Segment>) -> Vec<Segment> {
+    // A stable sort keeps the order of the records for equal addresses
+    segments.sort_by_key(|s| s.address);
+    let mut merged: Vec<Segment> = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if let Some(last) = merged.last_mut() {
+            let last_end = last.address + last.data.len();
+            if segment.address <= last_end {
+                let start = segment.address - last.address;
+                let overlap = (last_end - segment.address).min(segment.data.len());
+                last.data[start..start + overlap].copy_from_slice(&segment.data[..overlap]);
+                last.data.extend_from_slice(&segment.data[overlap..]);
+                continue;
+            }
+        }
+        merged.push(segment);
+    }
+    merged
+}
+
+fn to_address(address: u64) -> Option<usize> {
+    if address <= usize::MAX as u64 {
+        Some(address as usize)
+    } else {
+        None
+    }
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
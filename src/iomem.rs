@@ -0,0 +1,285 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Physical memory map from `/proc/iomem`
+//!
+//! The resource tree can be used to look up the address of a device by name
+//! and to check that a physical range does not include System RAM, holes in
+//! the memory map or only part of a resource. Passing an `Iomem` to
+//! `MappingBuilder::iomem` refuses mappings with any of these issues, while
+//! `Iomem::check` returns them, so that they can be reported as warnings.
+//!
+//! The kernel shows the addresses in `/proc/iomem` only to privileged users.
+
+use crate::{Error, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Range of physical addresses claimed by a device or by the kernel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resource {
+    /// First address of the resource
+    pub start: u64,
+    /// Last address of the resource (inclusive)
+    pub end: u64,
+    pub name: String,
+    /// Resources nested inside this one
+    pub children: Vec<Resource>,
+}
+
+impl Resource {
+    /// Size of the resource in bytes
+    ///
+    /// The size is a `u128`, as a resource spanning the whole 64-bit address
+    /// space is 2^64 bytes long.
+    pub fn size(&self) -> u128 {
+        u128::from(self.end - self.start) + 1
+    }
+
+    /// Check whether the resource is System RAM
+    pub fn is_system_ram(&self) -> bool {
+        self.name == "System RAM"
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    fn contains(&self, start: u64, end: u64) -> bool {
+        self.start <= start && end <= self.end
+    }
+}
+
+/// Problem of a physical range found by `Iomem::check`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// The range overlaps System RAM
+    SystemRam { start: u64, end: u64 },
+    /// The range covers only part of a resource, without being contained in
+    /// it
+    CrossesBoundary { name: String, start: u64, end: u64 },
+    /// Part of the range is not claimed by any resource
+    Unclaimed { start: u64, end: u64 },
+    /// The addresses are hidden because the process is not privileged
+    AddressesHidden,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::SystemRam { start, end } => {
+                write!(f, "range overlaps System RAM at {:#x}-{:#x}", start, end)
+            }
+            Issue::CrossesBoundary { name, start, end } => write!(
+                f,
+                "range crosses the boundary of {} at {:#x}-{:#x}",
+                name, start, end
+            ),
+            Issue::Unclaimed { start, end } => {
+                write!(
+                    f,
+                    "range {:#x}-{:#x} is not claimed by any resource",
+                    start, end
+                )
+            }
+            Issue::AddressesHidden => write!(f, "addresses in /proc/iomem are hidden"),
+        }
+    }
+}
+
+/// Tree of the resources listed in `/proc/iomem`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Iomem {
+    resources: Vec<Resource>,
+}
+
+impl Iomem {
+    /// Load the resource tree from `/proc/iomem`
+    pub fn load() -> Result<Iomem> {
+        Iomem::load_from("/proc/iomem")
+    }
+
+    /// Load the resource tree from a file in the `/proc/iomem` format
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Iomem> {
+        let text = fs::read_to_string(path).map_err(Error::Io)?;
+        Iomem::parse(&text)
+    }
+
+    /// Parse a resource tree in the `/proc/iomem` format
+    ///
+    /// Each line has the format `start-end : name`, with addresses in
+    /// hexadecimal, indented by two spaces for each nesting level.
+    pub fn parse(text: &str) -> Result<Iomem> {
+        // Stack of the resources being built, one for each nesting level
+        let mut stack: Vec<Resource> = Vec::new();
+        let mut resources = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error =
+                |message: &str| Error::Parse(format!("iomem line {}: {}", index + 1, message));
+
+            let trimmed = line.trim_start();
+            let depth = (line.len() - trimmed.len()) / 2;
+            let (range, name) = trimmed
+                .split_once(" : ")
+                .ok_or_else(|| error("missing ' : ' separator"))?;
+            let (start, end) = range
+                .split_once('-')
+                .ok_or_else(|| error("missing '-' in the address range"))?;
+            let start = u64::from_str_radix(start.trim(), 16)
+                .map_err(|_| error("invalid start address"))?;
+            let end =
+                u64::from_str_radix(end.trim(), 16).map_err(|_| error("invalid end address"))?;
+            if end < start {
+                return Err(error("end address before start address"));
+            }
+            if depth > stack.len() {
+                return Err(error("resource nested without a parent"));
+            }
+
+            while stack.len() > depth {
+                close_resource(&mut stack, &mut resources);
+            }
+            stack.push(Resource {
+                start,
+                end,
+                name: name.trim().to_string(),
+                children: Vec::new(),
+            });
+        }
+        while !stack.is_empty() {
+            close_resource(&mut stack, &mut resources);
+        }
+
+        Ok(Iomem { resources })
+    }
+
+    /// Top-level resources
+    pub fn resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    /// Find the first resource, at any depth, whose name contains `name`
+    ///
+    /// Resources of device tree nodes are named after the node, like
+    /// `fe215040.serial`, so they can be found with the node name.
+    pub fn find(&self, name: &str) -> Option<&Resource> {
+        self.find_all(name).into_iter().next()
+    }
+
+    /// Find all the resources, at any depth, whose name contains `name`
+    pub fn find_all(&self, name: &str) -> Vec<&Resource> {
+        let mut found = Vec::new();
+        let mut pending: Vec<&Resource> = self.resources.iter().rev().collect();
+        while let Some(resource) = pending.pop() {
+            if resource.name.contains(name) {
+                found.push(resource);
+            }
+            pending.extend(resource.children.iter().rev());
+        }
+        found
+    }
+
+    /// Return the issues of mapping `len` bytes starting at `physical_addr`
+    pub fn check(&self, physical_addr: usize, len: usize) -> Vec<Issue> {
+        if len == 0 {
+            return Vec::new();
+        }
+        if self.addresses_hidden() {
+            return vec![Issue::AddressesHidden];
+        }
+
+        let start = physical_addr as u64;
+        let end = start.saturating_add(len as u64 - 1);
+        let mut issues = Vec::new();
+
+        let mut pending: Vec<&Resource> = self.resources.iter().collect();
+        while let Some(resource) = pending.pop() {
+            if !resource.overlaps(start, end) {
+                continue;
+            }
+            if resource.is_system_ram() {
+                issues.push(Issue::SystemRam {
+                    start: resource.start,
+                    end: resource.end,
+                });
+            }
+            let contains_range = resource.contains(start, end);
+            let contained_in_range = start <= resource.start && resource.end <= end;
+            if !contains_range && !contained_in_range {
+                issues.push(Issue::CrossesBoundary {
+                    name: resource.name.clone(),
+                    start: resource.start,
+                    end: resource.end,
+                });
+            }
+            pending.extend(resource.children.iter());
+        }
+
+        // Look for the holes between the top-level resources overlapping the
+        // range
+        let mut claimed: Vec<&Resource> = self
+            .resources
+            .iter()
+            .filter(|r| r.overlaps(start, end))
+            .collect();
+        claimed.sort_by_key(|r| r.start);
+        // First address of the range not covered yet, if any
+        let mut next = Some(start);
+        for resource in claimed {
+            let uncovered = match next {
+                Some(uncovered) => uncovered,
+                None => break,
+            };
+            if resource.start > uncovered {
+                issues.push(Issue::Unclaimed {
+                    start: uncovered,
+                    end: resource.start - 1,
+                });
+            }
+            next = if resource.end >= end {
+                None
+            } else {
+                Some(uncovered.max(resource.end + 1))
+            };
+        }
+        if let Some(uncovered) = next {
+            issues.push(Issue::Unclaimed {
+                start: uncovered,
+                end,
+            });
+        }
+
+        issues
+    }
+
+    /// Check that `len` bytes starting at `physical_addr` can be mapped
+    /// without any of the issues returned by `check`
+    pub fn validate(&self, physical_addr: usize, len: usize) -> Result<()> {
+        match self.check(physical_addr, len).into_iter().next() {
+            Some(issue) => Err(Error::Iomem(issue)),
+            None => Ok(()),
+        }
+    }
+
+    fn addresses_hidden(&self) -> bool {
+        !self.resources.is_empty() && self.resources.iter().all(|r| r.start == 0 && r.end == 0)
+    }
+}
+
+/// Pop the innermost resource of the stack and add it to its parent
+fn close_resource(stack: &mut Vec<Resource>, resources: &mut Vec<Resource>) {
+    if let Some(resource) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(resource),
+            None => resources.push(resource),
+        }
+    }
+}
@@ -4,9 +4,44 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+#[macro_use]
+mod region;
+
+mod cache;
+mod error;
+mod memory;
+mod shared;
+mod view;
+mod word;
+
+pub mod barrier;
+pub mod batch;
+pub mod dma;
+pub mod fdt;
+pub mod golden;
+pub mod image;
+pub mod iomem;
+pub mod pagemap;
+pub mod pci;
+pub mod poll;
+pub mod register;
+pub mod sim;
+#[cfg(feature = "svd")]
+pub mod svd;
+pub mod trace;
+pub mod uio;
+
+pub use error::{Error, Result};
+pub use memory::{MemoryRead, MemoryWrite};
+pub use shared::SharedMapping;
+pub use view::{MappingView, MappingViewMut};
+pub use word::Word;
+
+use region::Region;
 use std::fs::OpenOptions;
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
 use std::ptr;
 
 /// Mapping between the virtual and physical address space
@@ -18,88 +53,371 @@ pub struct Mapping {
 }
 
 impl Mapping {
-    /// Create a new mapping of `len` bytes, starting at `physical_addr`
-    pub unsafe fn new(physical_addr: usize, len: usize) -> std::io::Result<Mapping> {
-        assert!(len > 0, "The mapping length must be greater than 0");
+    /// Create a new mapping of `len` bytes of `/dev/mem`, starting at `physical_addr`
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the physical address range can be accessed
+    /// without affecting memory in use by the kernel or by other processes.
+    pub unsafe fn new(physical_addr: usize, len: usize) -> Result<Mapping> {
+        Mapping::builder().offset(physical_addr).len(len).map()
+    }
+
+    /// Create a builder for mapping a region of an arbitrary device or file
+    pub fn builder() -> MappingBuilder {
+        MappingBuilder::default()
+    }
+
+    /// Create a new read-only mapping of `len` bytes of `/dev/mem`, starting
+    /// at `physical_addr`
+    ///
+    /// # Safety
+    ///
+    /// See `Mapping::new`.
+    pub unsafe fn new_readonly(physical_addr: usize, len: usize) -> Result<ReadOnlyMapping> {
+        Mapping::builder()
+            .offset(physical_addr)
+            .len(len)
+            .map_readonly()
+    }
+
+    /// Take ownership of `len` bytes mapped at `map_base`, which are
+    /// unmapped on drop
+    pub(crate) unsafe fn from_raw(map_base: *mut libc::c_void, len: usize) -> Mapping {
+        Mapping {
+            map_base,
+            len,
+            slice_base: map_base as *mut u8,
+            slice_max_len: len,
+        }
+    }
+
+    impl_read_api!();
+    impl_write_api!();
+
+    /// Overlay the register block `T` on the start of the mapping
+    ///
+    /// The mapping must be at least as long as `T` and correctly aligned for
+    /// it. The registers are accessed through the volatile cells in
+    /// `devmem::register`.
+    pub fn as_register_block<T: register::RegisterBlock>(&self) -> Result<&T> {
+        unsafe { self.region().register_block() }
+    }
+
+    /// Borrow `len` bytes of the mapping starting at `offset` for reading
+    ///
+    /// The offsets of the returned window are relative to `offset`.
+    pub fn window(&self, offset: usize, len: usize) -> Result<MappingView<'_>> {
+        Ok(MappingView::new(self.region().window(offset, len)?))
+    }
+
+    /// Borrow `len` bytes of the mapping starting at `offset` for reading and
+    /// writing
+    ///
+    /// The offsets of the returned window are relative to `offset`.
+    pub fn window_mut(&mut self, offset: usize, len: usize) -> Result<MappingViewMut<'_>> {
+        Ok(MappingViewMut::new(self.region().window(offset, len)?))
+    }
+
+    /// Split the mapping into two disjoint windows, before and after `mid`
+    ///
+    /// The windows can be split further, to give each sub-driver access to
+    /// its own registers only.
+    pub fn split_at_mut(&mut self, mid: usize) -> Result<(MappingViewMut<'_>, MappingViewMut<'_>)> {
+        let (first, second) = self.region().split_at(mid)?;
+        Ok((MappingViewMut::new(first), MappingViewMut::new(second)))
+    }
+
+    fn region(&self) -> Region {
+        unsafe { Region::new(self.slice_base, self.slice_max_len) }
+    }
+}
+
+// The mapping owns its pages, which can be accessed and unmapped from any
+// thread. It is not Sync, as register blocks overlaid on it through `&self`
+// must not be shared between threads, see `SharedMapping` instead.
+unsafe impl Send for Mapping {}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let _ = unsafe { libc::munmap(self.map_base, self.len) };
+    }
+}
+
+/// Read-only mapping between the virtual and physical address space
+///
+/// The device is opened with O_RDONLY and mapped with PROT_READ only, so
+/// only the read half of the `Mapping` API is available.
+pub struct ReadOnlyMapping {
+    map_base: *mut libc::c_void,
+    len: libc::size_t,
+    slice_base: *mut u8,
+    slice_max_len: libc::size_t,
+}
+
+impl ReadOnlyMapping {
+    impl_read_api!();
+
+    /// Borrow `len` bytes of the mapping starting at `offset`
+    ///
+    /// The offsets of the returned window are relative to `offset`.
+    pub fn window(&self, offset: usize, len: usize) -> Result<MappingView<'_>> {
+        Ok(MappingView::new(self.region().window(offset, len)?))
+    }
+
+    fn region(&self) -> Region {
+        unsafe { Region::new(self.slice_base, self.slice_max_len) }
+    }
+}
+
+unsafe impl Send for ReadOnlyMapping {}
+
+impl Drop for ReadOnlyMapping {
+    fn drop(&mut self) {
+        let _ = unsafe { libc::munmap(self.map_base, self.len) };
+    }
+}
+
+/// Memory type, or cache attribute, of a mapping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryType {
+    /// Uncached accesses, in order and of the requested width, as needed for
+    /// registers
+    ///
+    /// The device is opened with O_SYNC, which makes the kernel map it
+    /// uncached on most architectures.
+    #[default]
+    Uncached,
+    /// Writes may be buffered and combined into bursts, while reads are
+    /// uncached, as suited for framebuffers
+    ///
+    /// Available only for PCI resource files, through their `resourceN_wc`
+    /// variant. Mapping other devices returns `Error::Unsupported`.
+    WriteCombining,
+    /// Cached accesses, as suited for large SRAM regions and buffers
+    ///
+    /// The device is opened without O_SYNC. The kernel may still map the
+    /// range uncached, for example on x86 when it is reserved with a
+    /// different type in the PAT. Use `flush_cache` and `invalidate_cache`
+    /// to share the memory with devices.
+    Cached,
+}
+
+/// Builder for a `Mapping` of an mmap-able character device or file
+///
+/// By default the builder maps `/dev/mem`, where the offset is the physical
+/// address. Other devices, like `/sys/bus/pci/devices/*/resourceN`,
+/// `/dev/uioN` or a plain file, can be selected with `device`.
+pub struct MappingBuilder {
+    device: PathBuf,
+    offset: usize,
+    len: usize,
+    iomem: Option<iomem::Iomem>,
+    memory_type: MemoryType,
+}
+
+impl Default for MappingBuilder {
+    fn default() -> MappingBuilder {
+        MappingBuilder {
+            device: PathBuf::from("/dev/mem"),
+            offset: 0,
+            len: 0,
+            iomem: None,
+            memory_type: MemoryType::default(),
+        }
+    }
+}
+
+impl MappingBuilder {
+    /// Set the path of the device or file to map
+    pub fn device<P: AsRef<Path>>(mut self, path: P) -> MappingBuilder {
+        self.device = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Set the offset in the device of the first mapped byte
+    pub fn offset(mut self, offset: usize) -> MappingBuilder {
+        self.offset = offset;
+        self
+    }
+
+    /// Set the number of bytes to map
+    pub fn len(mut self, len: usize) -> MappingBuilder {
+        self.len = len;
+        self
+    }
+
+    /// Refuse to map ranges that overlap System RAM, holes in the memory map
+    /// or part of a resource in `iomem`
+    ///
+    /// The check compares the offset with physical addresses, so it is
+    /// meaningful only when mapping `/dev/mem`.
+    pub fn iomem(mut self, iomem: iomem::Iomem) -> MappingBuilder {
+        self.iomem = Some(iomem);
+        self
+    }
+
+    /// Set the memory type of the mapping, `MemoryType::Uncached` by default
+    pub fn memory_type(mut self, memory_type: MemoryType) -> MappingBuilder {
+        self.memory_type = memory_type;
+        self
+    }
+
+    /// Create the mapping
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the mapped range of the device can be
+    /// accessed without affecting memory in use by the kernel or by other
+    /// processes.
+    pub unsafe fn map(self) -> Result<Mapping> {
+        let (map_base, map_len, slice_base) = self.mmap(true)?;
+        Ok(Mapping {
+            map_base,
+            len: map_len,
+            slice_base,
+            slice_max_len: self.len,
+        })
+    }
+
+    /// Create a read-only mapping
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that reading the mapped range of the device
+    /// has no side effects on the kernel or on other processes.
+    pub unsafe fn map_readonly(self) -> Result<ReadOnlyMapping> {
+        let (map_base, map_len, slice_base) = self.mmap(false)?;
+        Ok(ReadOnlyMapping {
+            map_base,
+            len: map_len,
+            slice_base,
+            slice_max_len: self.len,
+        })
+    }
+
+    /// Map the device and return the base address and length of the mapped
+    /// pages, and the virtual address mapped to the offset
+    unsafe fn mmap(&self, writable: bool) -> Result<(*mut libc::c_void, usize, *mut u8)> {
+        let len = self.len;
+        if len == 0 {
+            return Err(Error::ZeroLength);
+        }
 
         // mmap() can map a file only at an offset that is a multiple of the
         // page size
-        // Compute the frame offset of the physical address and the starting
-        // address of the corresponding page frame
+        // Compute the frame offset of the requested offset and the starting
+        // offset of the corresponding page frame
         let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
-        let frame_offset = physical_addr % page_size;
-        let frame_addr = physical_addr - frame_offset;
+        let frame_offset = self.offset % page_size;
+        let frame_addr = self.offset - frame_offset;
+
+        // The whole range must be addressable, both in the device and in the
+        // virtual address space
+        self.offset.checked_add(len).ok_or(Error::Overflow)?;
+        if let Some(iomem) = &self.iomem {
+            iomem.validate(self.offset, len)?;
+        }
+        let map_len = len.checked_add(frame_offset).ok_or(Error::Overflow)?;
+        if frame_addr > libc::off_t::MAX as usize {
+            return Err(Error::Overflow);
+        }
 
-        // Open /dev/mem with O_RDWR and O_SYNC flags
-        let devmem_file = OpenOptions::new()
-            .write(true)
+        // Open the device with either O_RDWR or O_RDONLY, and with O_SYNC for
+        // uncached mappings
+        let (access_mode, prot) = if writable {
+            (libc::O_RDWR, libc::PROT_READ | libc::PROT_WRITE)
+        } else {
+            (libc::O_RDONLY, libc::PROT_READ)
+        };
+        let (device, sync) = match self.memory_type {
+            MemoryType::Uncached => (self.device.clone(), libc::O_SYNC),
+            MemoryType::WriteCombining => (self.write_combining_device()?, 0),
+            MemoryType::Cached => (self.device.clone(), 0),
+        };
+        let device_file = OpenOptions::new()
+            .write(writable)
             .read(true)
-            .custom_flags(libc::O_RDWR | libc::O_SYNC)
-            .open("/dev/mem")?;
+            .custom_flags(access_mode | sync)
+            .open(&device)
+            .map_err(Error::Open)?;
 
-        let devmem_fd = devmem_file.as_raw_fd();
+        let device_fd = device_file.as_raw_fd();
 
-        // Mmap /dev/mem in the virtual address space, starting at offset in
+        // Mmap the device in the virtual address space, starting at offset in
         // the file equal to the frame address
         // map_base points to the virtual address mapped to frame_addr
         let map_base = libc::mmap(
             ptr::null_mut(),
-            len + frame_offset,
-            libc::PROT_READ | libc::PROT_WRITE,
+            map_len,
+            prot,
             libc::MAP_SHARED,
-            devmem_fd,
+            device_fd,
             frame_addr as libc::off_t,
         );
 
-        // slice_base points to the virtual address mapped to physical_addr
-        let slice_base = (map_base as *mut u8).add(frame_offset);
-
-        Ok(Mapping {
-            map_base,
-            len: len + frame_offset,
-            slice_base,
-            slice_max_len: len,
-        })
-    }
-
-    /// Copy a slice of bytes from the physical address space into `dst`
-    pub fn copy_into_slice(&self, dst: &mut [u8]) {
-        assert!(self.slice_max_len >= dst.len());
-        let mapped_slice = self.as_slice(dst.len());
-        dst.copy_from_slice(mapped_slice);
-    }
-
-    /// Copy a slice of bytes from `src` to the physical address space
-    pub fn copy_from_slice(&mut self, src: &[u8]) {
-        assert!(self.slice_max_len >= src.len());
-        let mapped_slice = self.as_mut_slice(src.len());
-        mapped_slice.copy_from_slice(src);
-    }
+        if map_base == libc::MAP_FAILED {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::EPERM) && self.device == Path::new("/dev/mem") {
+                return Err(Error::StrictDevmem);
+            }
+            return Err(Error::Mmap(err));
+        }
 
-    fn as_slice(&self, slice_len: usize) -> &[u8] {
-        unsafe { std::slice::from_raw_parts(self.slice_base, slice_len) }
-    }
+        // slice_base points to the virtual address mapped to the offset
+        let slice_base = (map_base as *mut u8).add(frame_offset);
 
-    fn as_mut_slice(&mut self, slice_len: usize) -> &mut [u8] {
-        unsafe { std::slice::from_raw_parts_mut(self.slice_base, slice_len) }
+        Ok((map_base, map_len, slice_base))
     }
-}
 
-impl Drop for Mapping {
-    fn drop(&mut self) {
-        let _ = unsafe { libc::munmap(self.map_base, self.len) };
+    /// Path of the write-combining variant of the device, which is a PCI
+    /// resource file
+    fn write_combining_device(&self) -> Result<PathBuf> {
+        let unsupported = || {
+            Error::Unsupported(format!(
+                "{} cannot be mapped with write-combining",
+                self.device.display()
+            ))
+        };
+        let file_name = self
+            .device
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(unsupported)?;
+        if file_name.starts_with("resource") && file_name.ends_with("_wc") {
+            return Ok(self.device.clone());
+        }
+        match file_name.strip_prefix("resource") {
+            Some(index) if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) => {
+                let path = self.device.with_file_name(format!("{}_wc", file_name));
+                if path.exists() {
+                    Ok(path)
+                } else {
+                    Err(unsupported())
+                }
+            }
+            _ => Err(unsupported()),
+        }
     }
 }
 
 /// Copy a slice of bytes from the physical address space, starting at `physical_addr`, into `dst`
-pub unsafe fn read_into_slice(physical_addr: usize, dst: &mut [u8]) -> std::io::Result<()> {
-    let map = Mapping::new(physical_addr, dst.len())?;
+///
+/// # Safety
+///
+/// See `Mapping::new`.
+pub unsafe fn read_into_slice(physical_addr: usize, dst: &mut [u8]) -> Result<()> {
+    let map = Mapping::new_readonly(physical_addr, dst.len())?;
     map.copy_into_slice(dst);
     Ok(())
 }
 
 /// Copy a slice of bytes from `src` into the physical address space, starting at `physical_addr`
-pub unsafe fn write_from_slice(physical_addr: usize, src: &[u8]) -> std::io::Result<()> {
+///
+/// # Safety
+///
+/// See `Mapping::new`.
+pub unsafe fn write_from_slice(physical_addr: usize, src: &[u8]) -> Result<()> {
     let mut map = Mapping::new(physical_addr, src.len())?;
     map.copy_from_slice(src);
     Ok(())
@@ -0,0 +1,119 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::dma::DmaBuffer;
+use crate::{Mapping, MappingView, MappingViewMut, ReadOnlyMapping, Result, SharedMapping, Word};
+
+/// Word accesses to memory, implemented by the mappings of this crate and by
+/// `sim::SimMemory`
+///
+/// Drivers written against `MemoryRead` and `MemoryWrite`, like the
+/// functions generated by `register!`, can be run on a simulated memory in
+/// tests and on a `Mapping` on the target.
+pub trait MemoryRead {
+    /// Read a `u8` at `offset`
+    fn read_u8(&self, offset: usize) -> Result<u8>;
+
+    /// Read a `u16` at `offset`
+    fn read_u16(&self, offset: usize) -> Result<u16>;
+
+    /// Read a `u32` at `offset`
+    fn read_u32(&self, offset: usize) -> Result<u32>;
+
+    /// Read a `u64` at `offset`
+    fn read_u64(&self, offset: usize) -> Result<u64>;
+
+    /// Read a `T` at `offset`
+    fn read<T: Word>(&self, offset: usize) -> Result<T>
+    where
+        Self: Sized,
+    {
+        T::read_from(self, offset)
+    }
+}
+
+/// Word accesses to writable memory
+pub trait MemoryWrite: MemoryRead {
+    /// Write a `u8` at `offset`
+    fn write_u8(&mut self, offset: usize, value: u8) -> Result<()>;
+
+    /// Write a `u16` at `offset`
+    fn write_u16(&mut self, offset: usize, value: u16) -> Result<()>;
+
+    /// Write a `u32` at `offset`
+    fn write_u32(&mut self, offset: usize, value: u32) -> Result<()>;
+
+    /// Write a `u64` at `offset`
+    fn write_u64(&mut self, offset: usize, value: u64) -> Result<()>;
+
+    /// Write a `T` at `offset`
+    fn write<T: Word>(&mut self, offset: usize, value: T) -> Result<()>
+    where
+        Self: Sized,
+    {
+        value.write_to(self, offset)
+    }
+}
+
+/// Implement `MemoryRead` by calling the inherent methods of the same name
+macro_rules! impl_memory_read {
+    ($($ty:ty),*) => {
+        $(
+            impl MemoryRead for $ty {
+                fn read_u8(&self, offset: usize) -> Result<u8> {
+                    <$ty>::read_u8(self, offset)
+                }
+
+                fn read_u16(&self, offset: usize) -> Result<u16> {
+                    <$ty>::read_u16(self, offset)
+                }
+
+                fn read_u32(&self, offset: usize) -> Result<u32> {
+                    <$ty>::read_u32(self, offset)
+                }
+
+                fn read_u64(&self, offset: usize) -> Result<u64> {
+                    <$ty>::read_u64(self, offset)
+                }
+            }
+        )*
+    };
+}
+
+/// Implement `MemoryWrite` by calling the inherent methods of the same name
+macro_rules! impl_memory_write {
+    ($($ty:ty),*) => {
+        $(
+            impl MemoryWrite for $ty {
+                fn write_u8(&mut self, offset: usize, value: u8) -> Result<()> {
+                    <$ty>::write_u8(self, offset, value)
+                }
+
+                fn write_u16(&mut self, offset: usize, value: u16) -> Result<()> {
+                    <$ty>::write_u16(self, offset, value)
+                }
+
+                fn write_u32(&mut self, offset: usize, value: u32) -> Result<()> {
+                    <$ty>::write_u32(self, offset, value)
+                }
+
+                fn write_u64(&mut self, offset: usize, value: u64) -> Result<()> {
+                    <$ty>::write_u64(self, offset, value)
+                }
+            }
+        )*
+    };
+}
+
+impl_memory_read!(
+    Mapping,
+    ReadOnlyMapping,
+    MappingView<'_>,
+    MappingViewMut<'_>,
+    DmaBuffer,
+    SharedMapping
+);
+impl_memory_write!(Mapping, MappingViewMut<'_>, DmaBuffer, SharedMapping);
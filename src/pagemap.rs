@@ -0,0 +1,146 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Virtual to physical address translation through `/proc/<pid>/pagemap`
+//!
+//! The kernel reports the page frame numbers only to processes with
+//! CAP_SYS_ADMIN. Other processes see a frame number of zero for every
+//! page, which `Pagemap::translate` reports as `Error::PfnHidden`.
+//!
+//! ```no_run
+//! use devmem::pagemap::Pagemap;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let buffer = vec![0u8; 4096];
+//! let pagemap = Pagemap::current()?;
+//! let physical_addr = pagemap.translate(buffer.as_ptr() as usize)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, Result};
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+const PAGEMAP_ENTRY_SIZE: u64 = 8;
+
+const PM_PFN_MASK: u64 = (1 << 55) - 1;
+const PM_SOFT_DIRTY: u64 = 1 << 55;
+const PM_MMAP_EXCLUSIVE: u64 = 1 << 56;
+const PM_FILE: u64 = 1 << 61;
+const PM_SWAP: u64 = 1 << 62;
+const PM_PRESENT: u64 = 1 << 63;
+
+/// Entry of the pagemap of a virtual page
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageEntry {
+    raw: u64,
+}
+
+impl PageEntry {
+    /// Raw 64-bit pagemap entry
+    pub fn raw(&self) -> u64 {
+        self.raw
+    }
+
+    /// Check whether the page is present in RAM
+    pub fn is_present(&self) -> bool {
+        self.raw & PM_PRESENT != 0
+    }
+
+    /// Check whether the page is swapped out
+    pub fn is_swapped(&self) -> bool {
+        self.raw & PM_SWAP != 0
+    }
+
+    /// Check whether the page is file-backed or shared anonymous memory
+    pub fn is_file_or_shared(&self) -> bool {
+        self.raw & PM_FILE != 0
+    }
+
+    /// Check whether the page is mapped only by this process
+    pub fn is_exclusive(&self) -> bool {
+        self.raw & PM_MMAP_EXCLUSIVE != 0
+    }
+
+    /// Check whether the page was written since the soft-dirty bits were
+    /// last cleared
+    pub fn is_soft_dirty(&self) -> bool {
+        self.raw & PM_SOFT_DIRTY != 0
+    }
+
+    /// Page frame number of a present page
+    ///
+    /// Returns `None` if the page is not present. The frame number is zero
+    /// when it is hidden from the process.
+    pub fn pfn(&self) -> Option<u64> {
+        if self.is_present() {
+            Some(self.raw & PM_PFN_MASK)
+        } else {
+            None
+        }
+    }
+}
+
+/// Pagemap of a process
+pub struct Pagemap {
+    file: File,
+    page_size: usize,
+}
+
+impl Pagemap {
+    /// Open the pagemap of the current process
+    pub fn current() -> Result<Pagemap> {
+        Pagemap::open_path("/proc/self/pagemap")
+    }
+
+    /// Open the pagemap of the process `pid`
+    pub fn open(pid: u32) -> Result<Pagemap> {
+        Pagemap::open_path(format!("/proc/{}/pagemap", pid))
+    }
+
+    /// Open a file in the pagemap format
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Pagemap> {
+        let file = File::open(path).map_err(Error::Open)?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize };
+        Ok(Pagemap { file, page_size })
+    }
+
+    /// Pagemap entry of the page containing `virtual_addr`
+    pub fn entry(&self, virtual_addr: usize) -> Result<PageEntry> {
+        let page = (virtual_addr / self.page_size) as u64;
+        let mut raw = [0; PAGEMAP_ENTRY_SIZE as usize];
+        self.file
+            .read_exact_at(&mut raw, page * PAGEMAP_ENTRY_SIZE)
+            .map_err(Error::Io)?;
+        Ok(PageEntry {
+            raw: u64::from_le_bytes(raw),
+        })
+    }
+
+    /// Translate `virtual_addr` into a physical address
+    ///
+    /// Fails with `Error::PageNotPresent` if the page is not in RAM, and with
+    /// `Error::PfnHidden` if the process is not allowed to see the page frame
+    /// numbers.
+    pub fn translate(&self, virtual_addr: usize) -> Result<u64> {
+        let entry = self.entry(virtual_addr)?;
+        let pfn = entry.pfn().ok_or(Error::PageNotPresent {
+            virtual_addr,
+            swapped: entry.is_swapped(),
+        })?;
+        if pfn == 0 {
+            return Err(Error::PfnHidden);
+        }
+        Ok(pfn * self.page_size as u64 + (virtual_addr % self.page_size) as u64)
+    }
+
+    /// Size of a page
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+}
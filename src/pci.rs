@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mapping of PCI BARs through the sysfs resource files
+//!
+//! ```no_run
+//! use devmem::pci::PciDevice;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let device = PciDevice::open("0000:03:00.0")?;
+//! device.enable()?;
+//! let bar0 = unsafe { device.map_bar(0)? };
+//! let id = bar0.read_u32(0)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, Mapping, Result};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const IORESOURCE_IO: u64 = 0x0000_0100;
+const IORESOURCE_MEM: u64 = 0x0000_0200;
+const IORESOURCE_PREFETCH: u64 = 0x0000_2000;
+const IORESOURCE_MEM_64: u64 = 0x0010_0000;
+
+/// Number of standard BARs of a PCI device
+const BAR_COUNT: usize = 6;
+
+/// Base address register of a PCI device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bar {
+    pub index: usize,
+    /// Physical address of the BAR
+    pub start: u64,
+    /// Size of the BAR in bytes
+    pub size: u64,
+    /// Resource flags reported by the kernel
+    pub flags: u64,
+}
+
+impl Bar {
+    /// Check whether the BAR is in the memory space
+    pub fn is_memory(&self) -> bool {
+        self.flags & IORESOURCE_MEM != 0
+    }
+
+    /// Check whether the BAR is in the I/O port space
+    pub fn is_io(&self) -> bool {
+        self.flags & IORESOURCE_IO != 0
+    }
+
+    /// Check whether the BAR is prefetchable
+    pub fn is_prefetchable(&self) -> bool {
+        self.flags & IORESOURCE_PREFETCH != 0
+    }
+
+    /// Check whether the BAR is a 64-bit memory BAR
+    pub fn is_64bit(&self) -> bool {
+        self.flags & IORESOURCE_MEM_64 != 0
+    }
+}
+
+/// PCI device in sysfs
+pub struct PciDevice {
+    path: PathBuf,
+    address: String,
+}
+
+impl PciDevice {
+    /// Open the PCI device at `address`, in the `domain:bus:device.function`
+    /// format, like `0000:03:00.0`
+    pub fn open(address: &str) -> Result<PciDevice> {
+        PciDevice::open_in("/sys", address)
+    }
+
+    /// Open the PCI device at `address` in a sysfs tree mounted at
+    /// `sysfs_root`
+    pub fn open_in<P: AsRef<Path>>(sysfs_root: P, address: &str) -> Result<PciDevice> {
+        let path = sysfs_root.as_ref().join("bus/pci/devices").join(address);
+        fs::metadata(path.join("resource")).map_err(Error::Io)?;
+        Ok(PciDevice {
+            path,
+            address: address.to_string(),
+        })
+    }
+
+    /// Address of the device
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Directory of the device in sysfs
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Implemented BARs of the device
+    pub fn bars(&self) -> Result<Vec<Bar>> {
+        let text = fs::read_to_string(self.path.join("resource")).map_err(Error::Io)?;
+        let mut bars = Vec::new();
+        for (index, line) in text.lines().take(BAR_COUNT).enumerate() {
+            let error = || Error::Parse(format!("invalid PCI resource line {:?}", line));
+            let values = line
+                .split_whitespace()
+                .map(parse_hex)
+                .collect::<Option<Vec<u64>>>()
+                .ok_or_else(error)?;
+            let (start, end, flags) = match values.as_slice() {
+                [start, end, flags] => (*start, *end, *flags),
+                _ => return Err(error()),
+            };
+            // Unimplemented BARs and the upper half of 64-bit BARs are all
+            // zeros
+            if end == 0 || end < start {
+                continue;
+            }
+            bars.push(Bar {
+                index,
+                start,
+                size: end - start + 1,
+                flags,
+            });
+        }
+        Ok(bars)
+    }
+
+    /// BAR at `index`
+    pub fn bar(&self, index: usize) -> Result<Bar> {
+        self.bars()?
+            .into_iter()
+            .find(|bar| bar.index == index)
+            .ok_or_else(|| Error::Unsupported(format!("BAR {} is not implemented", index)))
+    }
+
+    /// Check whether the device is enabled
+    pub fn is_enabled(&self) -> Result<bool> {
+        let text = fs::read_to_string(self.path.join("enable")).map_err(Error::Io)?;
+        Ok(text.trim() != "0")
+    }
+
+    /// Enable the device, so that it responds to accesses to its BARs
+    pub fn enable(&self) -> Result<()> {
+        fs::write(self.path.join("enable"), "1").map_err(Error::Io)
+    }
+
+    /// Path of the resource file of the BAR at `index`
+    pub fn resource_path(&self, index: usize) -> PathBuf {
+        self.path.join(format!("resource{}", index))
+    }
+
+    /// Path of the write-combining resource file of the BAR at `index`, if
+    /// the kernel provides it
+    ///
+    /// The kernel creates it only for prefetchable memory BARs on
+    /// architectures supporting write-combining.
+    pub fn resource_wc_path(&self, index: usize) -> Option<PathBuf> {
+        let path = self.path.join(format!("resource{}_wc", index));
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Map the whole BAR at `index`
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that accessing the BAR does not interfere with
+    /// the driver bound to the device, if any.
+    pub unsafe fn map_bar(&self, index: usize) -> Result<Mapping> {
+        let len = self.memory_bar_len(index)?;
+        Mapping::builder()
+            .device(self.resource_path(index))
+            .len(len)
+            .map()
+    }
+
+    /// Map the whole BAR at `index` with write-combining
+    ///
+    /// # Safety
+    ///
+    /// See `map_bar`.
+    pub unsafe fn map_bar_wc(&self, index: usize) -> Result<Mapping> {
+        let len = self.memory_bar_len(index)?;
+        let path = self.resource_wc_path(index).ok_or_else(|| {
+            Error::Unsupported(format!("BAR {} has no write-combining resource", index))
+        })?;
+        Mapping::builder().device(path).len(len).map()
+    }
+
+    fn memory_bar_len(&self, index: usize) -> Result<usize> {
+        let bar = self.bar(index)?;
+        if !bar.is_memory() {
+            return Err(Error::Unsupported(format!(
+                "BAR {} is not a memory BAR",
+                index
+            )));
+        }
+        usize::try_from(bar.size).map_err(|_| Error::Overflow)
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u64> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u64::from_str_radix(digits, 16).ok()
+}
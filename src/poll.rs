@@ -0,0 +1,140 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Polling of registers with a timeout
+//!
+//! ```no_run
+//! use devmem::poll::Poller;
+//! use devmem::Mapping;
+//! use std::time::Duration;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mapping = unsafe { Mapping::new(0x1000_0000, 0x100)? };
+//! // Wait for the ready bit
+//! mapping.poll_until(0x4, 0x1u32, 0x1, Duration::from_millis(10))?;
+//!
+//! // Wait for the FIFO level to drop below 4, without sleeping
+//! let poller = Poller::new(Duration::from_micros(100)).busy_wait();
+//! mapping.poll_until_with(0x8, &poller, |level: u32| level < 4)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, MemoryRead, Result, Word};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Wait between two reads
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Wait {
+    /// Spin without giving up the CPU
+    Busy,
+    /// Sleep for `interval`, doubled after each read up to `max_interval`
+    Sleep {
+        interval: Duration,
+        max_interval: Duration,
+    },
+}
+
+/// Settings of a polling loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Poller {
+    timeout: Duration,
+    wait: Wait,
+}
+
+impl Poller {
+    /// Poll for at most `timeout`, sleeping between the reads with an
+    /// exponential backoff from 10 us to 1 ms
+    pub fn new(timeout: Duration) -> Poller {
+        Poller {
+            timeout,
+            wait: Wait::Sleep {
+                interval: Duration::from_micros(10),
+                max_interval: Duration::from_millis(1),
+            },
+        }
+    }
+
+    /// Spin between the reads, for the shortest latency
+    pub fn busy_wait(mut self) -> Poller {
+        self.wait = Wait::Busy;
+        self
+    }
+
+    /// Sleep for `interval` between the reads
+    pub fn interval(mut self, interval: Duration) -> Poller {
+        self.wait = Wait::Sleep {
+            interval,
+            max_interval: interval,
+        };
+        self
+    }
+
+    /// Sleep between the reads for `initial`, then twice as long after each
+    /// read, up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Poller {
+        self.wait = Wait::Sleep {
+            interval: initial,
+            max_interval: max.max(initial),
+        };
+        self
+    }
+
+    /// Read the `T` at `offset` until the bits selected by `mask` are equal
+    /// to the same bits of `value`, and return the value read
+    ///
+    /// The bits of `value` outside `mask` are ignored. Fails with
+    /// `Error::Timeout` and the last value read if the bits do not match
+    /// within the timeout.
+    pub fn poll_until<M, T>(&self, memory: &M, offset: usize, mask: T, value: T) -> Result<T>
+    where
+        M: MemoryRead + ?Sized,
+        T: Word,
+    {
+        let (mask, value) = (mask.to_u64(), value.to_u64());
+        self.poll(memory, offset, |read: T| {
+            (read.to_u64() ^ value) & mask == 0
+        })
+    }
+
+    /// Read the `T` at `offset` until `predicate` returns true for the value
+    /// read, and return that value
+    ///
+    /// Fails with `Error::Timeout` and the last value read if `predicate`
+    /// does not return true within the timeout.
+    pub fn poll<M, T, F>(&self, memory: &M, offset: usize, mut predicate: F) -> Result<T>
+    where
+        M: MemoryRead + ?Sized,
+        T: Word,
+        F: FnMut(T) -> bool,
+    {
+        let start = Instant::now();
+        let mut wait = self.wait;
+        loop {
+            let read = T::read_from(memory, offset)?;
+            if predicate(read) {
+                return Ok(read);
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= self.timeout {
+                return Err(Error::Timeout {
+                    last: read.to_u64(),
+                });
+            }
+            match &mut wait {
+                Wait::Busy => std::hint::spin_loop(),
+                Wait::Sleep {
+                    interval,
+                    max_interval,
+                } => {
+                    thread::sleep((*interval).min(self.timeout - elapsed));
+                    *interval = interval.saturating_mul(2).min(*max_interval);
+                }
+            }
+        }
+    }
+}
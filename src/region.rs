@@ -0,0 +1,301 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::register::RegisterBlock;
+use crate::{cache, Error, Result, Word};
+use std::ptr;
+
+/// Range of mapped memory shared by all the types that give access to it
+///
+/// A `Region` does not own the memory it points to. The owner guarantees
+/// that `base` points to `len` bytes that stay mapped while the region is
+/// in use.
+#[derive(Clone, Copy)]
+pub(crate) struct Region {
+    base: *mut u8,
+    len: usize,
+}
+
+impl Region {
+    pub(crate) unsafe fn new(base: *mut u8, len: usize) -> Region {
+        Region { base, len }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Sub-region of `len` bytes starting at `offset`
+    pub(crate) fn window(&self, offset: usize, len: usize) -> Result<Region> {
+        let base = self.range_ptr(offset, len)? as *mut u8;
+        Ok(Region { base, len })
+    }
+
+    /// Split the region into the sub-regions before and after `mid`
+    pub(crate) fn split_at(&self, mid: usize) -> Result<(Region, Region)> {
+        Ok((self.window(0, mid)?, self.window(mid, self.len - mid)?))
+    }
+
+    pub(crate) fn copy_into_slice(&self, dst: &mut [u8]) {
+        assert!(self.len >= dst.len());
+        let mapped_slice = unsafe { std::slice::from_raw_parts(self.base, dst.len()) };
+        dst.copy_from_slice(mapped_slice);
+    }
+
+    pub(crate) fn copy_from_slice(&self, src: &[u8]) {
+        assert!(self.len >= src.len());
+        let mapped_slice = unsafe { std::slice::from_raw_parts_mut(self.base, src.len()) };
+        mapped_slice.copy_from_slice(src);
+    }
+
+    pub(crate) fn read<T: Word>(&self, offset: usize) -> Result<T> {
+        let ptr = self.word_ptr::<T>(offset)?;
+        Ok(unsafe { ptr::read_volatile(ptr) })
+    }
+
+    pub(crate) fn write<T: Word>(&self, offset: usize, value: T) -> Result<()> {
+        let ptr = self.word_ptr::<T>(offset)?;
+        unsafe { ptr::write_volatile(ptr, value) };
+        Ok(())
+    }
+
+    pub(crate) fn flush_cache(&self, offset: usize, len: usize) -> Result<()> {
+        cache::flush(self.range_ptr(offset, len)?, len)
+    }
+
+    pub(crate) fn invalidate_cache(&self, offset: usize, len: usize) -> Result<()> {
+        cache::invalidate(self.range_ptr(offset, len)?, len)
+    }
+
+    /// Check that a register block fits at the start of the region and is
+    /// correctly aligned, and return a reference to it
+    ///
+    /// The caller must ensure the region is writable, and the lifetime of the
+    /// returned reference must not outlive the memory owner.
+    pub(crate) unsafe fn register_block<'a, T: RegisterBlock>(&self) -> Result<&'a T> {
+        let size = std::mem::size_of::<T>();
+        if size > self.len {
+            return Err(Error::OutOfBounds {
+                offset: 0,
+                len: size,
+            });
+        }
+
+        let align = std::mem::align_of::<T>();
+        if !(self.base as usize).is_multiple_of(align) {
+            return Err(Error::Misaligned { offset: 0, align });
+        }
+
+        Ok(&*(self.base as *const T))
+    }
+
+    /// Check that `len` bytes at `offset` are within the region, and return
+    /// a pointer to the first one
+    fn range_ptr(&self, offset: usize, len: usize) -> Result<*const u8> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => Ok(unsafe { self.base.add(offset) }),
+            _ => Err(Error::OutOfBounds { offset, len }),
+        }
+    }
+
+    /// Check that a `T` at `offset` is aligned and within the region, and
+    /// return a pointer to it
+    fn word_ptr<T: Word>(&self, offset: usize) -> Result<*mut T> {
+        let out_of_bounds = Error::OutOfBounds {
+            offset,
+            len: T::SIZE,
+        };
+        match offset.checked_add(T::SIZE) {
+            Some(end) if end <= self.len => {}
+            _ => return Err(out_of_bounds),
+        }
+
+        // Mappings start at a page boundary, so the alignment of the virtual
+        // address matches the alignment in the device
+        let ptr = unsafe { self.base.add(offset) };
+        if !(ptr as usize).is_multiple_of(T::SIZE) {
+            return Err(Error::Misaligned {
+                offset,
+                align: T::SIZE,
+            });
+        }
+
+        Ok(ptr as *mut T)
+    }
+}
+
+/// Implement the read half of the access API on a type with a
+/// `fn region(&self) -> Region` method
+macro_rules! impl_read_api {
+    () => {
+        /// Copy a slice of bytes from the mapped memory into `dst`
+        pub fn copy_into_slice(&self, dst: &mut [u8]) {
+            self.region().copy_into_slice(dst)
+        }
+
+        /// Read a `T` at `offset` bytes from the start of the mapping
+        ///
+        /// The read is performed with a single volatile load of the width of
+        /// `T`, so it is suitable for accessing memory-mapped registers.
+        /// `offset` must be aligned to the size of `T`.
+        pub fn read<T: $crate::Word>(&self, offset: usize) -> $crate::Result<T> {
+            self.region().read(offset)
+        }
+
+        /// Read a `u8` at `offset` with a single volatile load
+        pub fn read_u8(&self, offset: usize) -> $crate::Result<u8> {
+            self.read(offset)
+        }
+
+        /// Read a `u16` at `offset` with a single volatile load
+        pub fn read_u16(&self, offset: usize) -> $crate::Result<u16> {
+            self.read(offset)
+        }
+
+        /// Read a `u32` at `offset` with a single volatile load
+        pub fn read_u32(&self, offset: usize) -> $crate::Result<u32> {
+            self.read(offset)
+        }
+
+        /// Read a `u64` at `offset` with a single volatile load
+        pub fn read_u64(&self, offset: usize) -> $crate::Result<u64> {
+            self.read(offset)
+        }
+
+        /// Invalidate the data cache for `len` bytes at `offset`, so that the
+        /// following reads see the data written to memory by a device
+        ///
+        /// Needed only on `MemoryType::Cached` mappings and DMA buffers. On
+        /// x86 and on aarch64 the dirty lines are also written back, as
+        /// discarding them is not allowed from user space. Other
+        /// architectures return `Error::Unsupported`.
+        pub fn invalidate_cache(&self, offset: usize, len: usize) -> $crate::Result<()> {
+            self.region().invalidate_cache(offset, len)
+        }
+
+        /// Read the `T` at `offset` until the bits selected by `mask` are
+        /// equal to the same bits of `value`, sleeping between the reads, and
+        /// return the value read
+        ///
+        /// The bits of `value` outside `mask` are ignored. Fails with
+        /// `Error::Timeout` and the last value read if the bits do not match
+        /// within `timeout`.
+        pub fn poll_until<T: $crate::Word>(
+            &self,
+            offset: usize,
+            mask: T,
+            value: T,
+            timeout: std::time::Duration,
+        ) -> $crate::Result<T> {
+            $crate::poll::Poller::new(timeout).poll_until(self, offset, mask, value)
+        }
+
+        /// Read the `T` at `offset` until `predicate` returns true for the
+        /// value read, waiting between the reads as set by `poller`
+        pub fn poll_until_with<T, F>(
+            &self,
+            offset: usize,
+            poller: &$crate::poll::Poller,
+            predicate: F,
+        ) -> $crate::Result<T>
+        where
+            T: $crate::Word,
+            F: FnMut(T) -> bool,
+        {
+            poller.poll(self, offset, predicate)
+        }
+
+        /// Order the reads before this call before the reads after it
+        ///
+        /// See `devmem::barrier::read`.
+        pub fn read_barrier(&self) {
+            $crate::barrier::read()
+        }
+
+        /// Complete all the accesses before this call before any access after
+        /// it
+        ///
+        /// See `devmem::barrier::full`.
+        pub fn barrier(&self) {
+            $crate::barrier::full()
+        }
+    };
+}
+
+/// Implement the write half of the access API on a type with a
+/// `fn region(&self) -> Region` method
+macro_rules! impl_write_api {
+    () => {
+        /// Copy a slice of bytes from `src` to the mapped memory
+        pub fn copy_from_slice(&mut self, src: &[u8]) {
+            self.region().copy_from_slice(src)
+        }
+
+        /// Write a `T` at `offset` bytes from the start of the mapping
+        ///
+        /// The write is performed with a single volatile store of the width
+        /// of `T`, so it is suitable for accessing memory-mapped registers.
+        /// `offset` must be aligned to the size of `T`.
+        pub fn write<T: $crate::Word>(&mut self, offset: usize, value: T) -> $crate::Result<()> {
+            self.region().write(offset, value)
+        }
+
+        /// Write a `u8` at `offset` with a single volatile store
+        pub fn write_u8(&mut self, offset: usize, value: u8) -> $crate::Result<()> {
+            self.write(offset, value)
+        }
+
+        /// Write a `u16` at `offset` with a single volatile store
+        pub fn write_u16(&mut self, offset: usize, value: u16) -> $crate::Result<()> {
+            self.write(offset, value)
+        }
+
+        /// Write a `u32` at `offset` with a single volatile store
+        pub fn write_u32(&mut self, offset: usize, value: u32) -> $crate::Result<()> {
+            self.write(offset, value)
+        }
+
+        /// Write a `u64` at `offset` with a single volatile store
+        pub fn write_u64(&mut self, offset: usize, value: u64) -> $crate::Result<()> {
+            self.write(offset, value)
+        }
+
+        /// Write the data cache for `len` bytes at `offset` back to memory, so
+        /// that a device reading the memory sees the previous writes
+        ///
+        /// Needed only on `MemoryType::Cached` mappings and DMA buffers.
+        /// Architectures without user space cache maintenance return
+        /// `Error::Unsupported`.
+        pub fn flush_cache(&mut self, offset: usize, len: usize) -> $crate::Result<()> {
+            self.region().flush_cache(offset, len)
+        }
+
+        /// Make the writes before this call visible to devices before the
+        /// writes after it
+        ///
+        /// See `devmem::barrier::write`.
+        pub fn write_barrier(&self) {
+            $crate::barrier::write()
+        }
+
+        /// Write a `T` at `offset`, then read it back, so that the write has
+        /// reached the device when the call returns
+        ///
+        /// Buses like PCI post the writes, which may reach the device much
+        /// later than the store completes on the CPU, while a read from the
+        /// same device returns only after the previous writes. If reading the
+        /// register has side effects, write it with `write` and read another
+        /// register of the device instead.
+        pub fn write_flush<T: $crate::Word>(
+            &mut self,
+            offset: usize,
+            value: T,
+        ) -> $crate::Result<()> {
+            self.region().write(offset, value)?;
+            self.region().read::<T>(offset).map(|_| ())
+        }
+    };
+}
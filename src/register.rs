@@ -0,0 +1,309 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Volatile register cells for overlaying `#[repr(C)]` register blocks on a
+//! `Mapping`
+//!
+//! ```no_run
+//! use devmem::register::{ReadOnly, ReadWrite, RegisterBlock, WriteOnly};
+//! use devmem::Mapping;
+//!
+//! #[repr(C)]
+//! struct Uart {
+//!     data: ReadWrite<u32>,
+//!     status: ReadOnly<u32>,
+//!     control: WriteOnly<u32>,
+//! }
+//!
+//! unsafe impl RegisterBlock for Uart {}
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mapping = unsafe { Mapping::new(0x1000_0000, 12)? };
+//! let uart = mapping.as_register_block::<Uart>()?;
+//! if uart.status.read() & 0x1 != 0 {
+//!     uart.data.write(0x55);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Word;
+use std::cell::UnsafeCell;
+use std::ptr;
+
+/// Type that can be overlaid on mapped memory by `Mapping::as_register_block`
+///
+/// # Safety
+///
+/// The implementing type must be `#[repr(C)]` (or `#[repr(transparent)]`)
+/// and must consist only of register cells, arrays of register cells, other
+/// register blocks and reserved padding that is never accessed.
+pub unsafe trait RegisterBlock {}
+
+/// Register that can only be read
+#[repr(transparent)]
+pub struct ReadOnly<T: Word> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Word> ReadOnly<T> {
+    /// Read the register with a single volatile load
+    pub fn read(&self) -> T {
+        unsafe { ptr::read_volatile(self.value.get()) }
+    }
+}
+
+/// Register that can only be written
+#[repr(transparent)]
+pub struct WriteOnly<T: Word> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Word> WriteOnly<T> {
+    /// Write the register with a single volatile store
+    pub fn write(&self, value: T) {
+        unsafe { ptr::write_volatile(self.value.get(), value) }
+    }
+}
+
+/// Register that can be read and written
+#[repr(transparent)]
+pub struct ReadWrite<T: Word> {
+    value: UnsafeCell<T>,
+}
+
+impl<T: Word> ReadWrite<T> {
+    /// Read the register with a single volatile load
+    pub fn read(&self) -> T {
+        unsafe { ptr::read_volatile(self.value.get()) }
+    }
+
+    /// Write the register with a single volatile store
+    pub fn write(&self, value: T) {
+        unsafe { ptr::write_volatile(self.value.get(), value) }
+    }
+
+    /// Read the register, update the value with `f` and write it back
+    pub fn modify<F: FnOnce(T) -> T>(&self, f: F) {
+        self.write(f(self.read()));
+    }
+}
+
+unsafe impl<T: Word> RegisterBlock for ReadOnly<T> {}
+unsafe impl<T: Word> RegisterBlock for WriteOnly<T> {}
+unsafe impl<T: Word> RegisterBlock for ReadWrite<T> {}
+unsafe impl<R: RegisterBlock, const N: usize> RegisterBlock for [R; N] {}
+
+/// Define a register with named bit fields at an offset in a `Mapping`
+///
+/// The macro generates a module named after the register, containing the
+/// `OFFSET` and `RESET` constants, an `R` type with a getter for each field,
+/// a `W` type with a setter for each field, and the `read`, `write` and
+/// `modify` operations allowed by the access kind of the register (`rw`,
+/// `ro` or `wo`).
+///
+/// Fields span the inclusive bit range `lsb..=msb`. A field can list the
+/// values it accepts as an enum, whose getter returns `None` for values not
+/// listed.
+///
+/// `write` starts from the reset value of the register, while `modify`
+/// starts from the value read from the register.
+///
+/// The operations accept any `MemoryRead` or `MemoryWrite`, so that the same
+/// driver code runs on a `Mapping`, on a window of a mapping or on a
+/// `sim::SimMemory` in tests.
+///
+/// ```no_run
+/// use devmem::{register, Mapping};
+///
+/// register! {
+///     /// Control register
+///     pub mod ctrl: u32 @ 0x04, reset = 0x0000_0000, rw {
+///         /// Enable the peripheral
+///         enable: 0..=0,
+///         /// Operating mode
+///         mode: 1..=2 => Mode {
+///             Idle = 0,
+///             Tx = 1,
+///             Rx = 2,
+///         },
+///         /// Clock divider
+///         div: 8..=15,
+///     }
+/// }
+///
+/// # fn main() -> devmem::Result<()> {
+/// let mut mapping = unsafe { Mapping::new(0x1000_0000, 0x100)? };
+/// ctrl::write(&mut mapping, |w| w.mode(ctrl::Mode::Tx).div(4))?;
+/// ctrl::modify(&mut mapping, |_, w| w.enable(1))?;
+/// assert_eq!(ctrl::read(&mapping)?.mode(), Some(ctrl::Mode::Tx));
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! register {
+    (@ops rw, $ty:ident) => {
+        $crate::register!(@ops ro, $ty);
+        $crate::register!(@ops wo, $ty);
+
+        /// Read the register, update the value with `f` and write it back
+        pub fn modify<M, F>(memory: &mut M, f: F) -> $crate::Result<()>
+        where
+            M: $crate::MemoryWrite + ?Sized,
+            F: for<'w> FnOnce(&R, &'w mut W) -> &'w mut W,
+        {
+            let r = read(memory)?;
+            let mut w = W { bits: r.bits };
+            f(&r, &mut w);
+            $crate::Word::write_to(w.bits, memory, OFFSET)
+        }
+    };
+    (@ops ro, $ty:ident) => {
+        /// Read the register with a single volatile load
+        pub fn read<M: $crate::MemoryRead + ?Sized>(memory: &M) -> $crate::Result<R> {
+            Ok(R {
+                bits: <$ty as $crate::Word>::read_from(memory, OFFSET)?,
+            })
+        }
+    };
+    (@ops wo, $ty:ident) => {
+        /// Write the register, starting from its reset value
+        pub fn write<M, F>(memory: &mut M, f: F) -> $crate::Result<()>
+        where
+            M: $crate::MemoryWrite + ?Sized,
+            F: FnOnce(&mut W) -> &mut W,
+        {
+            let mut w = W { bits: RESET };
+            f(&mut w);
+            $crate::Word::write_to(w.bits, memory, OFFSET)
+        }
+    };
+    (@mask $ty:ident, $lsb:literal, $msb:literal) => {
+        ($ty::MAX >> ($ty::BITS - 1 - ($msb - $lsb)))
+    };
+    (@get $ty:ident, $field:ident, $lsb:literal, $msb:literal, $(#[$fmeta:meta])*) => {
+        $(#[$fmeta])*
+        pub fn $field(&self) -> $ty {
+            (self.bits >> $lsb) & $crate::register!(@mask $ty, $lsb, $msb)
+        }
+    };
+    (@get $ty:ident, $field:ident, $lsb:literal, $msb:literal, $(#[$fmeta:meta])*, $enum:ident) => {
+        $(#[$fmeta])*
+        pub fn $field(&self) -> Option<$enum> {
+            $enum::from_bits((self.bits >> $lsb) & $crate::register!(@mask $ty, $lsb, $msb))
+        }
+    };
+    (@set $ty:ident, $field:ident, $lsb:literal, $msb:literal, $(#[$fmeta:meta])*) => {
+        $(#[$fmeta])*
+        pub fn $field(&mut self, value: $ty) -> &mut W {
+            let mask = $crate::register!(@mask $ty, $lsb, $msb);
+            self.bits = (self.bits & !(mask << $lsb)) | ((value & mask) << $lsb);
+            self
+        }
+    };
+    (@set $ty:ident, $field:ident, $lsb:literal, $msb:literal, $(#[$fmeta:meta])*, $enum:ident) => {
+        $(#[$fmeta])*
+        pub fn $field(&mut self, value: $enum) -> &mut W {
+            let mask = $crate::register!(@mask $ty, $lsb, $msb);
+            self.bits = (self.bits & !(mask << $lsb)) | ((value.bits() & mask) << $lsb);
+            self
+        }
+    };
+    (
+        $(#[$meta:meta])*
+        $vis:vis mod $name:ident : $ty:ident @ $offset:expr, reset = $reset:expr, $access:ident {
+            $(
+                $(#[$fmeta:meta])*
+                $field:ident : $lsb:literal ..= $msb:literal $(=> $enum:ident {
+                    $(
+                        $(#[$vmeta:meta])*
+                        $variant:ident = $value:literal
+                    ),* $(,)?
+                })?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[allow(dead_code)]
+        $vis mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            /// Offset of the register in the mapping
+            pub const OFFSET: usize = $offset;
+            /// Reset value of the register
+            pub const RESET: $ty = $reset;
+
+            $(
+                const _: () = assert!($lsb <= $msb && $msb < $ty::BITS, "invalid bit range");
+            )*
+
+            /// Value read from the register
+            #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+            pub struct R {
+                bits: $ty,
+            }
+
+            impl R {
+                /// Raw value of the register
+                pub fn bits(&self) -> $ty {
+                    self.bits
+                }
+
+                $(
+                    $crate::register!(@get $ty, $field, $lsb, $msb, $(#[$fmeta])* $(, $enum)?);
+                )*
+            }
+
+            /// Value to be written to the register
+            #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+            pub struct W {
+                bits: $ty,
+            }
+
+            impl W {
+                /// Set the raw value of the register
+                pub fn bits(&mut self, bits: $ty) -> &mut W {
+                    self.bits = bits;
+                    self
+                }
+
+                $(
+                    $crate::register!(@set $ty, $field, $lsb, $msb, $(#[$fmeta])* $(, $enum)?);
+                )*
+            }
+
+            $($(
+                #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+                pub enum $enum {
+                    $(
+                        $(#[$vmeta])*
+                        $variant,
+                    )*
+                }
+
+                impl $enum {
+                    /// Value of the field
+                    pub fn bits(self) -> $ty {
+                        match self {
+                            $($enum::$variant => $value,)*
+                        }
+                    }
+
+                    /// Field value from its raw bits, if it is defined
+                    pub fn from_bits(bits: $ty) -> Option<$enum> {
+                        match bits {
+                            $($value => Some($enum::$variant),)*
+                            _ => None,
+                        }
+                    }
+                }
+            )?)*
+
+            $crate::register!(@ops $access, $ty);
+        }
+    };
+}
@@ -0,0 +1,148 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::region::Region;
+use crate::{Mapping, Result, Word};
+use std::sync::Arc;
+
+/// Mapping shared between threads
+///
+/// Cloning a `SharedMapping` creates another handle to the same mapping,
+/// which is unmapped when the last handle is dropped. All accesses go through
+/// `&self` and are single volatile loads or stores of an aligned word, like
+/// the `readl` and `writel` of the Linux kernel, and the same race model
+/// applies: concurrent accesses to the same register are issued to the
+/// device in some order, and an aligned access of at most the native word
+/// size is single-copy atomic on the supported architectures, so it does not
+/// tear. `u64` accesses on 32-bit architectures may be split into two 32-bit
+/// accesses.
+///
+/// This relies on the hardware rather than on the Rust memory model, in
+/// which racing volatile accesses are data races. On device memory the
+/// compiler cannot observe the race, but on RAM or on a file mapped in the
+/// same process, threads racing on the same word should use atomics or a
+/// lock instead.
+///
+/// Sequences of accesses are not atomic. A read-modify-write of a register
+/// shared by several threads, or a write to an index register followed by
+/// an access to the data register, must be protected by a lock held by the
+/// callers. Copies of slices, which the CPU may split into accesses of any
+/// width, are not available on a shared mapping.
+///
+/// ```
+/// use devmem::{Mapping, SharedMapping};
+/// use std::thread;
+///
+/// # fn main() -> devmem::Result<()> {
+/// # let path = std::env::temp_dir().join(format!("devmem-shared-{}", std::process::id()));
+/// # std::fs::write(&path, vec![0; 4096]).unwrap();
+/// let mapping = unsafe { Mapping::builder().device(&path).len(4096).map()? };
+/// let shared = SharedMapping::new(mapping);
+///
+/// let workers: Vec<_> = (0..4)
+///     .map(|index| {
+///         let shared = shared.clone();
+///         thread::spawn(move || {
+///             for value in 0..1000 {
+///                 shared.write_u32(index * 4, value)?;
+///             }
+///             shared.read_u32(index * 4)
+///         })
+///     })
+///     .collect();
+/// for worker in workers {
+///     assert_eq!(worker.join().unwrap()?, 999);
+/// }
+/// # std::fs::remove_file(&path).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SharedMapping {
+    mapping: Arc<SyncMapping>,
+}
+
+/// Mapping accessed only with volatile word-sized accesses through `&self`
+struct SyncMapping(Mapping);
+
+// None of the `Mapping` methods that are unsafe to call from several threads
+// at once, like `as_register_block`, are reachable through `SharedMapping`
+unsafe impl Sync for SyncMapping {}
+
+impl SharedMapping {
+    /// Share `mapping` between threads
+    pub fn new(mapping: Mapping) -> SharedMapping {
+        SharedMapping {
+            mapping: Arc::new(SyncMapping(mapping)),
+        }
+    }
+
+    /// Read a `T` at `offset` bytes from the start of the mapping with a
+    /// single volatile load
+    ///
+    /// `offset` must be aligned to the size of `T`.
+    pub fn read<T: Word>(&self, offset: usize) -> Result<T> {
+        self.region().read(offset)
+    }
+
+    /// Read a `u8` at `offset` with a single volatile load
+    pub fn read_u8(&self, offset: usize) -> Result<u8> {
+        self.read(offset)
+    }
+
+    /// Read a `u16` at `offset` with a single volatile load
+    pub fn read_u16(&self, offset: usize) -> Result<u16> {
+        self.read(offset)
+    }
+
+    /// Read a `u32` at `offset` with a single volatile load
+    pub fn read_u32(&self, offset: usize) -> Result<u32> {
+        self.read(offset)
+    }
+
+    /// Read a `u64` at `offset` with a single volatile load
+    pub fn read_u64(&self, offset: usize) -> Result<u64> {
+        self.read(offset)
+    }
+
+    /// Write a `T` at `offset` bytes from the start of the mapping with a
+    /// single volatile store
+    ///
+    /// `offset` must be aligned to the size of `T`.
+    pub fn write<T: Word>(&self, offset: usize, value: T) -> Result<()> {
+        self.region().write(offset, value)
+    }
+
+    /// Write a `u8` at `offset` with a single volatile store
+    pub fn write_u8(&self, offset: usize, value: u8) -> Result<()> {
+        self.write(offset, value)
+    }
+
+    /// Write a `u16` at `offset` with a single volatile store
+    pub fn write_u16(&self, offset: usize, value: u16) -> Result<()> {
+        self.write(offset, value)
+    }
+
+    /// Write a `u32` at `offset` with a single volatile store
+    pub fn write_u32(&self, offset: usize, value: u32) -> Result<()> {
+        self.write(offset, value)
+    }
+
+    /// Write a `u64` at `offset` with a single volatile store
+    pub fn write_u64(&self, offset: usize, value: u64) -> Result<()> {
+        self.write(offset, value)
+    }
+
+    fn region(&self) -> Region {
+        self.mapping.0.region()
+    }
+}
+
+impl From<Mapping> for SharedMapping {
+    fn from(mapping: Mapping) -> SharedMapping {
+        SharedMapping::new(mapping)
+    }
+}
@@ -0,0 +1,313 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Simulated memory for testing drivers without the hardware
+//!
+//! A `SimMemory` implements `MemoryRead` and `MemoryWrite` on regions of
+//! plain memory, where registers with side effects can be modelled with
+//! callbacks. Every access is recorded, so that a test can check the
+//! sequence of accesses of a driver.
+//!
+//! ```
+//! use devmem::sim::{Access, SimMemory};
+//! use devmem::{MemoryRead, MemoryWrite};
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mut memory = SimMemory::new();
+//! memory
+//!     .add_region(0x0, 0x100)
+//!     .write_1_to_clear(0x10)
+//!     .fifo(0x20, vec![0x41, 0x42]);
+//! memory.poke::<u32>(0x10, 0b101)?;
+//!
+//! memory.write_u32(0x10, 0b001)?;
+//! assert_eq!(memory.read_u32(0x10)?, 0b100);
+//! assert_eq!(memory.read_u8(0x20)?, 0x41);
+//!
+//! assert_eq!(
+//!     memory.accesses(),
+//!     vec![
+//!         Access::write(0x10, 0b001u32),
+//!         Access::read(0x10, 0b100u32),
+//!         Access::read(0x20, 0x41u8),
+//!     ]
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Error, MemoryRead, MemoryWrite, Result, Word};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// Direction of an access
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// Access recorded by `SimMemory`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub offset: usize,
+    /// Width of the access in bytes
+    pub width: usize,
+    /// Value read or written, zero-extended
+    pub value: u64,
+}
+
+impl Access {
+    /// Read of `value` at `offset`, with the width of `T`
+    pub fn read<T: Word>(offset: usize, value: T) -> Access {
+        Access {
+            kind: AccessKind::Read,
+            offset,
+            width: T::SIZE,
+            value: value.to_u64(),
+        }
+    }
+
+    /// Write of `value` at `offset`, with the width of `T`
+    pub fn write<T: Word>(offset: usize, value: T) -> Access {
+        Access {
+            kind: AccessKind::Write,
+            offset,
+            width: T::SIZE,
+            value: value.to_u64(),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+        };
+        write!(
+            f,
+            "{} {:#x} u{} {:#0width$x}",
+            kind,
+            self.offset,
+            self.width * 8,
+            self.value,
+            width = self.width * 2 + 2
+        )
+    }
+}
+
+type ReadHook = Box<dyn FnMut(&mut u64) -> u64>;
+type WriteHook = Box<dyn FnMut(&mut u64, u64)>;
+
+/// Simulated memory with registers modelled by callbacks
+#[derive(Default)]
+pub struct SimMemory {
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Start offset and content of each region
+    regions: Vec<(usize, Vec<u8>)>,
+    read_hooks: HashMap<usize, ReadHook>,
+    write_hooks: HashMap<usize, WriteHook>,
+    accesses: Vec<Access>,
+}
+
+impl SimMemory {
+    /// Create a simulated memory without any region
+    pub fn new() -> SimMemory {
+        SimMemory::default()
+    }
+
+    /// Add `len` bytes of zeroed memory starting at `offset`
+    ///
+    /// Accesses outside the regions return `Error::OutOfBounds`.
+    pub fn add_region(&mut self, offset: usize, len: usize) -> &mut SimMemory {
+        self.state.get_mut().regions.push((offset, vec![0; len]));
+        self
+    }
+
+    /// Call `hook` on each read at `offset`
+    ///
+    /// The hook receives the stored value, which it can modify, and returns
+    /// the value read.
+    pub fn on_read<F>(&mut self, offset: usize, hook: F) -> &mut SimMemory
+    where
+        F: FnMut(&mut u64) -> u64 + 'static,
+    {
+        self.state
+            .get_mut()
+            .read_hooks
+            .insert(offset, Box::new(hook));
+        self
+    }
+
+    /// Call `hook` on each write at `offset`, instead of storing the value
+    ///
+    /// The hook receives the stored value, which it can modify, and the
+    /// value written.
+    pub fn on_write<F>(&mut self, offset: usize, hook: F) -> &mut SimMemory
+    where
+        F: FnMut(&mut u64, u64) + 'static,
+    {
+        self.state
+            .get_mut()
+            .write_hooks
+            .insert(offset, Box::new(hook));
+        self
+    }
+
+    /// Make the register at `offset` clear the bits written as 1
+    pub fn write_1_to_clear(&mut self, offset: usize) -> &mut SimMemory {
+        self.on_write(offset, |stored, value| *stored &= !value)
+    }
+
+    /// Make the register at `offset` clear itself when read
+    pub fn read_to_clear(&mut self, offset: usize) -> &mut SimMemory {
+        self.on_read(offset, |stored| std::mem::replace(stored, 0))
+    }
+
+    /// Make each read of the register at `offset` return the next of
+    /// `values`, then the stored value once they are exhausted
+    pub fn fifo<I: IntoIterator<Item = u64>>(
+        &mut self,
+        offset: usize,
+        values: I,
+    ) -> &mut SimMemory {
+        let mut values: VecDeque<u64> = values.into_iter().collect();
+        self.on_read(offset, move |stored| values.pop_front().unwrap_or(*stored))
+    }
+
+    /// Read a `T` at `offset` without calling the hooks or recording the
+    /// access, as the simulated device would
+    pub fn peek<T: Word>(&self, offset: usize) -> Result<T> {
+        self.state.borrow().load::<T>(offset).map(T::from_u64)
+    }
+
+    /// Write a `T` at `offset` without calling the hooks or recording the
+    /// access, as the simulated device would
+    pub fn poke<T: Word>(&mut self, offset: usize, value: T) -> Result<()> {
+        self.state.get_mut().store::<T>(offset, value.to_u64())
+    }
+
+    /// Accesses recorded since the creation or the last call to
+    /// `clear_accesses`
+    pub fn accesses(&self) -> Vec<Access> {
+        self.state.borrow().accesses.clone()
+    }
+
+    /// Forget the recorded accesses
+    pub fn clear_accesses(&mut self) {
+        self.state.get_mut().accesses.clear();
+    }
+
+    fn read_word<T: Word>(&self, offset: usize) -> Result<T> {
+        let mut state = self.state.borrow_mut();
+        let mut stored = state.load::<T>(offset)?;
+        let value = match state.read_hooks.get_mut(&offset) {
+            Some(hook) => hook(&mut stored),
+            None => stored,
+        };
+        state.store::<T>(offset, stored)?;
+        let value = T::from_u64(value);
+        state.accesses.push(Access::read(offset, value));
+        Ok(value)
+    }
+
+    fn write_word<T: Word>(&mut self, offset: usize, value: T) -> Result<()> {
+        let state = self.state.get_mut();
+        let mut stored = state.load::<T>(offset)?;
+        match state.write_hooks.get_mut(&offset) {
+            Some(hook) => hook(&mut stored, value.to_u64()),
+            None => stored = value.to_u64(),
+        }
+        state.store::<T>(offset, stored)?;
+        state.accesses.push(Access::write(offset, value));
+        Ok(())
+    }
+}
+
+impl State {
+    /// Index of the region and range of the bytes of the word of type `T` at
+    /// `offset`, with the same checks as the accesses to a `Mapping`
+    fn locate<T: Word>(&self, offset: usize) -> Result<(usize, std::ops::Range<usize>)> {
+        if !offset.is_multiple_of(T::SIZE) {
+            return Err(Error::Misaligned {
+                offset,
+                align: T::SIZE,
+            });
+        }
+        let out_of_bounds = Error::OutOfBounds {
+            offset,
+            len: T::SIZE,
+        };
+        let end = match offset.checked_add(T::SIZE) {
+            Some(end) => end,
+            None => return Err(out_of_bounds),
+        };
+        self.regions
+            .iter()
+            .position(|(start, data)| *start <= offset && end <= start + data.len())
+            .map(|index| {
+                let start = self.regions[index].0;
+                (index, offset - start..end - start)
+            })
+            .ok_or(out_of_bounds)
+    }
+
+    fn load<T: Word>(&self, offset: usize) -> Result<u64> {
+        let (index, range) = self.locate::<T>(offset)?;
+        let mut bytes = [0; 8];
+        bytes[..T::SIZE].copy_from_slice(&self.regions[index].1[range]);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn store<T: Word>(&mut self, offset: usize, value: u64) -> Result<()> {
+        let (index, range) = self.locate::<T>(offset)?;
+        self.regions[index].1[range].copy_from_slice(&value.to_le_bytes()[..T::SIZE]);
+        Ok(())
+    }
+}
+
+impl MemoryRead for SimMemory {
+    fn read_u8(&self, offset: usize) -> Result<u8> {
+        self.read_word(offset)
+    }
+
+    fn read_u16(&self, offset: usize) -> Result<u16> {
+        self.read_word(offset)
+    }
+
+    fn read_u32(&self, offset: usize) -> Result<u32> {
+        self.read_word(offset)
+    }
+
+    fn read_u64(&self, offset: usize) -> Result<u64> {
+        self.read_word(offset)
+    }
+}
+
+impl MemoryWrite for SimMemory {
+    fn write_u8(&mut self, offset: usize, value: u8) -> Result<()> {
+        self.write_word(offset, value)
+    }
+
+    fn write_u16(&mut self, offset: usize, value: u16) -> Result<()> {
+        self.write_word(offset, value)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) -> Result<()> {
+        self.write_word(offset, value)
+    }
+
+    fn write_u64(&mut self, offset: usize, value: u64) -> Result<()> {
+        self.write_word(offset, value)
+    }
+}
@@ -0,0 +1,698 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Generate register maps from CMSIS-SVD files
+//!
+//! Each peripheral of the device becomes a module with its base address,
+//! the size of its address block, a `map` function returning a `Mapping` of
+//! the peripheral and one `register!` definition per register.
+//!
+//! Registers of a size other than 8, 16, 32 or 64 bits, and registers,
+//! fields and enumerated values whose name is already taken after
+//! conversion to a Rust identifier, are left out with a `// skipped:`
+//! comment in the generated code.
+//!
+//! The generator can be called from a build script:
+//!
+//! ```ignore
+//! // build.rs
+//! let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+//! devmem::svd::generate_file("soc.svd", out_dir.join("soc.rs")).unwrap();
+//!
+//! // src/soc.rs
+//! include!(concat!(env!("OUT_DIR"), "/soc.rs"));
+//! ```
+//!
+//! or through the `svd2devmem` binary.
+
+use roxmltree::{Document, Node};
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Errors returned while generating a register map
+#[derive(Debug)]
+pub enum Error {
+    /// The SVD file could not be read or the output could not be written
+    Io(io::Error),
+    /// The SVD file is not well-formed XML
+    Xml(roxmltree::Error),
+    /// The SVD file is missing a required element or has an invalid value
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Xml(err) => write!(f, "invalid XML: {}", err),
+            Error::Invalid(msg) => write!(f, "invalid SVD: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Xml(err) => Some(err),
+            Error::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(err: roxmltree::Error) -> Error {
+        Error::Xml(err)
+    }
+}
+
+/// Read the SVD file at `input` and write the generated Rust code to `output`
+pub fn generate_file<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<(), Error> {
+    let svd = fs::read_to_string(input)?;
+    fs::write(output, generate(&svd)?)?;
+    Ok(())
+}
+
+/// Generate the Rust code describing the peripherals of an SVD file
+pub fn generate(svd: &str) -> Result<String, Error> {
+    let doc = Document::parse(svd)?;
+    let device = doc.root_element();
+    if !device.has_tag_name("device") {
+        return Err(Error::Invalid("root element is not <device>".to_string()));
+    }
+
+    let device_name = child_text(device, "name").unwrap_or("device");
+    let defaults = Properties::default().inherit(device)?;
+
+    let peripherals = device
+        .children()
+        .find(|n| n.has_tag_name("peripherals"))
+        .ok_or_else(|| Error::Invalid("missing <peripherals>".to_string()))?;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "// Register map of {} generated by devmem::svd. Do not edit.",
+        device_name
+    )
+    .unwrap();
+
+    let elements: Vec<Node> = peripherals
+        .children()
+        .filter(|n| n.has_tag_name("peripheral"))
+        .collect();
+    for peripheral in &elements {
+        let derived = match peripheral.attribute("derivedFrom") {
+            Some(base) => Some(
+                elements
+                    .iter()
+                    .find(|n| child_text(**n, "name") == Some(base))
+                    .copied()
+                    .ok_or_else(|| {
+                        Error::Invalid(format!("unknown peripheral {} in derivedFrom", base))
+                    })?,
+            ),
+            None => None,
+        };
+        let p = parse_peripheral(*peripheral, derived, &defaults)?;
+        emit_peripheral(&mut out, &p);
+    }
+
+    Ok(out)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+/// Register properties inherited from the device, peripherals and clusters
+#[derive(Clone, Copy)]
+struct Properties {
+    size: u64,
+    access: Access,
+    reset: u64,
+}
+
+impl Default for Properties {
+    fn default() -> Properties {
+        Properties {
+            size: 32,
+            access: Access::ReadWrite,
+            reset: 0,
+        }
+    }
+}
+
+impl Properties {
+    fn inherit(mut self, node: Node) -> Result<Properties, Error> {
+        if let Some(size) = child_number(node, "size")? {
+            self.size = size;
+        }
+        if let Some(access) = child_text(node, "access") {
+            self.access = parse_access(access)?;
+        }
+        if let Some(reset) = child_number(node, "resetValue")? {
+            self.reset = reset;
+        }
+        Ok(self)
+    }
+}
+
+struct Peripheral {
+    name: String,
+    description: Option<String>,
+    base: u64,
+    size: u64,
+    registers: Vec<Register>,
+}
+
+struct Register {
+    name: String,
+    description: Option<String>,
+    offset: u64,
+    properties: Properties,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    name: String,
+    description: Option<String>,
+    lsb: u64,
+    msb: u64,
+    values: Vec<EnumValue>,
+}
+
+struct EnumValue {
+    name: String,
+    description: Option<String>,
+    value: u64,
+}
+
+fn parse_peripheral(
+    node: Node,
+    derived: Option<Node>,
+    defaults: &Properties,
+) -> Result<Peripheral, Error> {
+    let name = required_text(node, "name")?.to_string();
+    let base = child_number(node, "baseAddress")?
+        .ok_or_else(|| Error::Invalid(format!("missing <baseAddress> in {}", name)))?;
+
+    // Elements not present in a derived peripheral are taken from the
+    // peripheral it derives from
+    let source = |tag: &str| {
+        node.children()
+            .find(|n| n.has_tag_name(tag))
+            .or_else(|| derived.and_then(|d| d.children().find(|n| n.has_tag_name(tag))))
+    };
+
+    let description = source("description").and_then(|n| n.text()).map(clean_text);
+    let mut properties = *defaults;
+    if let Some(d) = derived {
+        properties = properties.inherit(d)?;
+    }
+    properties = properties.inherit(node)?;
+
+    let mut registers = Vec::new();
+    if let Some(block) = source("registers") {
+        parse_registers(block, 0, "", &properties, &mut registers)?;
+    }
+
+    // The size of the peripheral is the end of its furthest address block,
+    // or of its furthest register when there are no address blocks
+    let mut size = 0;
+    let blocks = node
+        .children()
+        .chain(derived.into_iter().flat_map(|d| d.children()))
+        .filter(|n| n.has_tag_name("addressBlock"));
+    for block in blocks {
+        let offset = child_number(block, "offset")?.unwrap_or(0);
+        let block_size = child_number(block, "size")?.unwrap_or(0);
+        size = size.max(offset + block_size);
+    }
+    for register in &registers {
+        size = size.max(register.offset + register.properties.size / 8);
+    }
+
+    Ok(Peripheral {
+        name,
+        description,
+        base,
+        size,
+        registers,
+    })
+}
+
+fn parse_registers(
+    parent: Node,
+    base_offset: u64,
+    prefix: &str,
+    properties: &Properties,
+    registers: &mut Vec<Register>,
+) -> Result<(), Error> {
+    for node in parent.children().filter(|n| n.is_element()) {
+        let is_cluster = node.has_tag_name("cluster");
+        if !is_cluster && !node.has_tag_name("register") {
+            continue;
+        }
+
+        let name = required_text(node, "name")?;
+        let offset = child_number(node, "addressOffset")?
+            .ok_or_else(|| Error::Invalid(format!("missing <addressOffset> in {}", name)))?;
+        let properties = properties.inherit(node)?;
+
+        for (name, offset) in expand_dim(node, name, offset)? {
+            let name = format!("{}{}", prefix, name);
+            if is_cluster {
+                let prefix = format!("{}_", name);
+                parse_registers(node, base_offset + offset, &prefix, &properties, registers)?;
+            } else {
+                let fields = parse_fields(node, &name, properties.size)?;
+                registers.push(Register {
+                    name,
+                    description: child_text(node, "description").map(clean_text),
+                    offset: base_offset + offset,
+                    properties,
+                    fields,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Expand the name and offset of an element with a `dim` array
+fn expand_dim(node: Node, name: &str, offset: u64) -> Result<Vec<(String, u64)>, Error> {
+    let dim = match child_number(node, "dim")? {
+        Some(dim) => dim,
+        None => return Ok(vec![(name.to_string(), offset)]),
+    };
+    let increment = child_number(node, "dimIncrement")?
+        .ok_or_else(|| Error::Invalid(format!("missing <dimIncrement> in {}", name)))?;
+    let indices: Vec<String> = match child_text(node, "dimIndex") {
+        Some(list) if list.contains('-') && !list.contains(',') => {
+            let mut bounds = list.splitn(2, '-').map(str::trim);
+            let first = bounds.next().unwrap_or("");
+            let last = bounds.next().unwrap_or("");
+            match (first.parse::<u64>(), last.parse::<u64>()) {
+                (Ok(first), Ok(last)) => (first..=last).map(|i| i.to_string()).collect(),
+                _ => {
+                    let first = first.bytes().next().unwrap_or(b'A');
+                    let last = last.bytes().next().unwrap_or(b'A');
+                    (first..=last).map(|c| (c as char).to_string()).collect()
+                }
+            }
+        }
+        Some(list) => list.split(',').map(|s| s.trim().to_string()).collect(),
+        None => (0..dim).map(|i| i.to_string()).collect(),
+    };
+
+    Ok(indices
+        .iter()
+        .enumerate()
+        .map(|(i, index)| {
+            let name = if name.contains("[%s]") {
+                name.replace("[%s]", index)
+            } else {
+                name.replace("%s", index)
+            };
+            (name, offset + i as u64 * increment)
+        })
+        .collect())
+}
+
+/// Parse the fields of `register`, which must fit in its `size` bits
+fn parse_fields(register: Node, register_name: &str, size: u64) -> Result<Vec<Field>, Error> {
+    let mut fields = Vec::new();
+    let nodes = register
+        .children()
+        .filter(|n| n.has_tag_name("fields"))
+        .flat_map(|n| n.children())
+        .filter(|n| n.has_tag_name("field"));
+    for node in nodes {
+        let name = required_text(node, "name")?.to_string();
+        let (lsb, msb) = if let Some(offset) = child_number(node, "bitOffset")? {
+            let width = child_number(node, "bitWidth")?.unwrap_or(1);
+            if width == 0 {
+                return Err(Error::Invalid(format!("<bitWidth> of 0 in {}", name)));
+            }
+            let msb = offset
+                .checked_add(width - 1)
+                .ok_or_else(|| Error::Invalid(format!("invalid bit range in {}", name)))?;
+            (offset, msb)
+        } else if let (Some(lsb), Some(msb)) =
+            (child_number(node, "lsb")?, child_number(node, "msb")?)
+        {
+            (lsb, msb)
+        } else if let Some(range) = child_text(node, "bitRange") {
+            parse_bit_range(range)
+                .ok_or_else(|| Error::Invalid(format!("invalid <bitRange> in {}", name)))?
+        } else {
+            return Err(Error::Invalid(format!("missing bit range in {}", name)));
+        };
+        if lsb > msb || msb >= size {
+            return Err(Error::Invalid(format!(
+                "field {} of {} spans bits {}..={}, outside of the {}-bit register",
+                name, register_name, lsb, msb, size
+            )));
+        }
+
+        let mut values: Vec<EnumValue> = Vec::new();
+        let nodes = node
+            .children()
+            .filter(|n| n.has_tag_name("enumeratedValues"))
+            .flat_map(|n| n.children())
+            .filter(|n| n.has_tag_name("enumeratedValue"));
+        for value in nodes {
+            // Values with "don't care" bits and default values cannot be
+            // expressed as a single match arm
+            let number = match child_text(value, "value").map(parse_number) {
+                Some(Some(number)) => number,
+                _ => continue,
+            };
+            if values.iter().any(|v| v.value == number) {
+                continue;
+            }
+            values.push(EnumValue {
+                name: required_text(value, "name")?.to_string(),
+                description: child_text(value, "description").map(clean_text),
+                value: number,
+            });
+        }
+
+        fields.push(Field {
+            name,
+            description: child_text(node, "description").map(clean_text),
+            lsb,
+            msb,
+            values,
+        });
+    }
+    Ok(fields)
+}
+
+fn emit_peripheral(out: &mut String, p: &Peripheral) {
+    let module = snake_case(&p.name);
+    writeln!(out).unwrap();
+    emit_doc(out, "", p.description.as_deref());
+    writeln!(out, "pub mod {} {{", module).unwrap();
+    writeln!(out, "    /// Base physical address of the peripheral").unwrap();
+    writeln!(out, "    pub const BASE_ADDRESS: usize = {:#x};", p.base).unwrap();
+    writeln!(out, "    /// Size of the address block of the peripheral").unwrap();
+    writeln!(out, "    pub const SIZE: usize = {:#x};", p.size).unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    /// Map the registers of the peripheral").unwrap();
+    writeln!(out, "    ///").unwrap();
+    writeln!(out, "    /// # Safety").unwrap();
+    writeln!(out, "    ///").unwrap();
+    writeln!(out, "    /// See `devmem::Mapping::new`.").unwrap();
+    writeln!(
+        out,
+        "    pub unsafe fn map() -> devmem::Result<devmem::Mapping> {{"
+    )
+    .unwrap();
+    writeln!(out, "        devmem::Mapping::new(BASE_ADDRESS, SIZE)").unwrap();
+    writeln!(out, "    }}").unwrap();
+
+    let mut emitted: Vec<String> = Vec::new();
+    for register in &p.registers {
+        let name = snake_case(&register.name);
+        let ty = match register.properties.size {
+            8 => "u8",
+            16 => "u16",
+            32 => "u32",
+            64 => "u64",
+            size => {
+                writeln!(out).unwrap();
+                writeln!(
+                    out,
+                    "    // skipped: register {} has an unsupported size of {} bits",
+                    register.name, size
+                )
+                .unwrap();
+                continue;
+            }
+        };
+        if emitted.contains(&name) {
+            writeln!(out).unwrap();
+            writeln!(
+                out,
+                "    // skipped: register {} at {:#x}, as {} is already defined",
+                register.name, register.offset, name
+            )
+            .unwrap();
+            continue;
+        }
+        emitted.push(name.clone());
+
+        let bits = register.properties.size;
+        let access = match register.properties.access {
+            Access::ReadOnly => "ro",
+            Access::WriteOnly => "wo",
+            Access::ReadWrite => "rw",
+        };
+        let reset = if bits == 64 {
+            register.properties.reset
+        } else {
+            register.properties.reset & ((1 << bits) - 1)
+        };
+
+        writeln!(out).unwrap();
+        writeln!(out, "    devmem::register! {{").unwrap();
+        emit_doc(out, "        ", register.description.as_deref());
+        writeln!(
+            out,
+            "        pub mod {}: {} @ {:#x}, reset = {:#x}, {} {{",
+            name, ty, register.offset, reset, access
+        )
+        .unwrap();
+
+        let mut field_names: Vec<String> = Vec::new();
+        for field in &register.fields {
+            let mut field_name = snake_case(&field.name);
+            if field_name == "bits" {
+                field_name.push('_');
+            }
+            if field_names.contains(&field_name) {
+                writeln!(
+                    out,
+                    "            // skipped: field {}, as {} is already defined",
+                    field.name, field_name
+                )
+                .unwrap();
+                continue;
+            }
+            field_names.push(field_name.clone());
+
+            let max = if field.msb - field.lsb == 63 {
+                u64::MAX
+            } else {
+                (1 << (field.msb - field.lsb + 1)) - 1
+            };
+            let (values, too_large): (Vec<&EnumValue>, Vec<&EnumValue>) =
+                field.values.iter().partition(|v| v.value <= max);
+            for value in too_large {
+                writeln!(
+                    out,
+                    "            // skipped: value {} = {:#x} of field {}, which does not fit in the field",
+                    value.name, value.value, field.name
+                )
+                .unwrap();
+            }
+
+            emit_doc(out, "            ", field.description.as_deref());
+            write!(
+                out,
+                "            {}: {}..={}",
+                field_name, field.lsb, field.msb
+            )
+            .unwrap();
+            if !values.is_empty() {
+                // The register module already defines the R and W types
+                let mut enum_name = camel_case(&field.name);
+                if enum_name == "R" || enum_name == "W" {
+                    enum_name.push_str("Field");
+                }
+                writeln!(out, " => {} {{", enum_name).unwrap();
+                let mut variants: Vec<String> = Vec::new();
+                for value in values {
+                    let variant = camel_case(&value.name);
+                    if variants.contains(&variant) {
+                        writeln!(
+                            out,
+                            "                // skipped: value {} = {:#x}, as {} is already defined",
+                            value.name, value.value, variant
+                        )
+                        .unwrap();
+                        continue;
+                    }
+                    emit_doc(out, "                ", value.description.as_deref());
+                    writeln!(out, "                {} = {:#x},", variant, value.value).unwrap();
+                    variants.push(variant);
+                }
+                write!(out, "            }}").unwrap();
+            }
+            writeln!(out, ",").unwrap();
+        }
+
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+    }
+
+    writeln!(out, "}}").unwrap();
+}
+
+fn emit_doc(out: &mut String, indent: &str, doc: Option<&str>) {
+    if let Some(doc) = doc {
+        if !doc.is_empty() {
+            writeln!(out, "{}/// {}", indent, doc).unwrap();
+        }
+    }
+}
+
+fn child_text<'a>(node: Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.has_tag_name(tag))
+        .and_then(|n| n.text())
+        .map(str::trim)
+}
+
+fn required_text<'a>(node: Node<'a, '_>, tag: &str) -> Result<&'a str, Error> {
+    child_text(node, tag)
+        .ok_or_else(|| Error::Invalid(format!("missing <{}> in <{}>", tag, node.tag_name().name())))
+}
+
+fn child_number(node: Node, tag: &str) -> Result<Option<u64>, Error> {
+    match child_text(node, tag) {
+        Some(text) => parse_number(text)
+            .map(Some)
+            .ok_or_else(|| Error::Invalid(format!("invalid number {:?} in <{}>", text, tag))),
+        None => Ok(None),
+    }
+}
+
+/// Parse a scaledNonNegativeInteger in decimal, hexadecimal or binary
+fn parse_number(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = text
+        .strip_prefix("0b")
+        .or_else(|| text.strip_prefix("0B"))
+        .or_else(|| text.strip_prefix('#'))
+    {
+        u64::from_str_radix(bin, 2).ok()
+    } else {
+        let (digits, scale) = match text.chars().last() {
+            Some('k') | Some('K') => (&text[..text.len() - 1], 1 << 10),
+            Some('m') | Some('M') => (&text[..text.len() - 1], 1 << 20),
+            Some('g') | Some('G') => (&text[..text.len() - 1], 1 << 30),
+            _ => (text, 1),
+        };
+        digits.parse::<u64>().ok()?.checked_mul(scale)
+    }
+}
+
+/// Parse a bit range in the `[msb:lsb]` format
+fn parse_bit_range(text: &str) -> Option<(u64, u64)> {
+    let inner = text.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut bounds = inner.splitn(2, ':');
+    let msb = bounds.next()?.trim().parse().ok()?;
+    let lsb = bounds.next()?.trim().parse().ok()?;
+    Some((lsb, msb))
+}
+
+fn parse_access(text: &str) -> Result<Access, Error> {
+    match text {
+        "read-only" => Ok(Access::ReadOnly),
+        "write-only" | "writeOnce" => Ok(Access::WriteOnly),
+        "read-write" | "read-writeOnce" => Ok(Access::ReadWrite),
+        _ => Err(Error::Invalid(format!("invalid access {:?}", text))),
+    }
+}
+
+fn clean_text(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "try",
+    "type", "unsafe", "use", "where", "while", "yield",
+];
+
+/// Split an SVD name in words, at underscores and lower to upper case
+/// transitions
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn snake_case(name: &str) -> String {
+    let mut ident = words(name)
+        .iter()
+        .map(|w| w.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.push('_');
+    }
+    ident
+}
+
+fn camel_case(name: &str) -> String {
+    let mut ident: String = words(name)
+        .iter()
+        .map(|w| {
+            let lower = w.to_ascii_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, 'V');
+    }
+    if ident == "Self" {
+        ident.push('_');
+    }
+    ident
+}
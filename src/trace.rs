@@ -0,0 +1,377 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tracing of the accesses to a mapping
+//!
+//! `Traced` wraps a `MemoryRead` or `MemoryWrite` and passes a `Record` of
+//! each access to a `TraceSink`: a `Vec<Record>`, a `TraceWriter` storing
+//! them in a compact binary format, or, with the `log` feature, a `LogSink`
+//! logging them. Recorded traces can be read back with `TraceReader` and
+//! re-issued with `replay`, or with `devmem replay FILE`.
+//!
+//! Only the word accesses of `MemoryRead` and `MemoryWrite` are traced.
+//! Copies of slices with `copy_into_slice` and `copy_from_slice` are not
+//! available through `Traced`, and copies made on the wrapped memory, for
+//! example through `Traced::memory`, are not recorded.
+//!
+//! ```no_run
+//! use devmem::trace::{TraceWriter, Traced};
+//! use devmem::{Mapping, MemoryWrite};
+//! use std::fs::File;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let mapping = unsafe { Mapping::new(0x1000_0000, 0x100)? };
+//! let file = File::create("bringup.trace").map_err(devmem::Error::Io)?;
+//! let mut traced = Traced::new(mapping, 0x1000_0000, TraceWriter::new(file)?);
+//! traced.write_u32(0x4, 0x1)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::sim::{Access, AccessKind};
+use crate::{Error, MemoryRead, MemoryWrite, Result};
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Magic number and version at the start of a binary trace
+const TRACE_HEADER: &[u8; 8] = b"DEVMEMT\x01";
+
+/// Bit of the kind byte of a record set for writes
+const KIND_WRITE: u8 = 0x80;
+
+/// Traced access
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// Time of the access since the start of the trace
+    pub timestamp: Duration,
+    /// Physical address of the access
+    pub address: u64,
+    /// Width of the access in bytes
+    pub width: usize,
+    pub kind: AccessKind,
+    /// Value read or written, zero-extended
+    pub value: u64,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read ",
+            AccessKind::Write => "write",
+        };
+        write!(
+            f,
+            "[{}.{:06}] {} {:#x} u{} = {:#0width$x}",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            kind,
+            self.address,
+            self.width * 8,
+            self.value,
+            width = self.width * 2 + 2
+        )
+    }
+}
+
+/// The access of a record, with its physical address as offset, so that
+/// traces can be compared with `golden::Golden`
+impl From<Record> for Access {
+    fn from(record: Record) -> Access {
+        Access {
+            kind: record.kind,
+            offset: record.address as usize,
+            width: record.width,
+            value: record.value,
+        }
+    }
+}
+
+/// Destination of the records of a `Traced` memory
+pub trait TraceSink {
+    fn record(&mut self, record: &Record);
+}
+
+impl TraceSink for Vec<Record> {
+    fn record(&mut self, record: &Record) {
+        self.push(*record);
+    }
+}
+
+/// Sink logging each record with `log::trace!`
+#[cfg(feature = "log")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+#[cfg(feature = "log")]
+impl TraceSink for LogSink {
+    fn record(&mut self, record: &Record) {
+        log::trace!(target: "devmem", "{}", record);
+    }
+}
+
+/// Sink writing the records in the binary trace format
+///
+/// The trace starts with an 8-byte header, followed by a record of 17 to 24
+/// bytes for each access: the timestamp in nanoseconds and the address as
+/// little-endian `u64`, a byte with the width in bytes and bit 7 set for
+/// writes, and the value as little-endian integer of the width of the
+/// access.
+///
+/// Write errors are kept and returned by `finish`, as sinks cannot fail.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Write the header of a trace to `out`
+    pub fn new(mut out: W) -> Result<TraceWriter<W>> {
+        out.write_all(TRACE_HEADER).map_err(Error::Io)?;
+        Ok(TraceWriter { out, error: None })
+    }
+
+    /// Flush the trace and return the writer, or the first write error
+    pub fn finish(mut self) -> Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(Error::Io(err));
+        }
+        self.out.flush().map_err(Error::Io)?;
+        Ok(self.out)
+    }
+
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let mut kind = record.width as u8;
+        if record.kind == AccessKind::Write {
+            kind |= KIND_WRITE;
+        }
+        self.out
+            .write_all(&(record.timestamp.as_nanos() as u64).to_le_bytes())?;
+        self.out.write_all(&record.address.to_le_bytes())?;
+        self.out.write_all(&[kind])?;
+        self.out
+            .write_all(&record.value.to_le_bytes()[..record.width])
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn record(&mut self, record: &Record) {
+        if self.error.is_none() {
+            self.error = self.write_record(record).err();
+        }
+    }
+}
+
+/// Iterator over the records of a binary trace
+pub struct TraceReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Check the header of the trace in `input`
+    pub fn new(mut input: R) -> Result<TraceReader<R>> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header).map_err(Error::Io)?;
+        if &header != TRACE_HEADER {
+            return Err(Error::Parse("not a devmem trace".to_string()));
+        }
+        Ok(TraceReader { input })
+    }
+
+    fn read_record(&mut self) -> Result<Option<Record>> {
+        let mut fixed = [0; 17];
+        let read = read_full(&mut self.input, &mut fixed).map_err(Error::Io)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let truncated = || Error::Parse("truncated trace record".to_string());
+        if read < fixed.len() {
+            return Err(truncated());
+        }
+
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&fixed[0..8]);
+        let mut address = [0; 8];
+        address.copy_from_slice(&fixed[8..16]);
+        let kind = fixed[16];
+        let width = (kind & !KIND_WRITE) as usize;
+        if ![1, 2, 4, 8].contains(&width) {
+            return Err(Error::Parse(format!("invalid access width {}", width)));
+        }
+        let mut value = [0; 8];
+        if read_full(&mut self.input, &mut value[..width]).map_err(Error::Io)? < width {
+            return Err(truncated());
+        }
+
+        Ok(Some(Record {
+            timestamp: Duration::from_nanos(u64::from_le_bytes(timestamp)),
+            address: u64::from_le_bytes(address),
+            width,
+            kind: if kind & KIND_WRITE != 0 {
+                AccessKind::Write
+            } else {
+                AccessKind::Read
+            },
+            value: u64::from_le_bytes(value),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Result<Record>> {
+        self.read_record().transpose()
+    }
+}
+
+/// Read until `buf` is full or the end of the input, and return the number
+/// of bytes read
+fn read_full<R: Read>(input: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Memory whose accesses are recorded in a `TraceSink`
+pub struct Traced<M, S: TraceSink> {
+    memory: M,
+    base: u64,
+    start: Instant,
+    sink: RefCell<S>,
+}
+
+impl<M, S: TraceSink> Traced<M, S> {
+    /// Trace the accesses to `memory`, whose offset 0 is at physical address
+    /// `base`
+    pub fn new(memory: M, base: u64, sink: S) -> Traced<M, S> {
+        Traced {
+            memory,
+            base,
+            start: Instant::now(),
+            sink: RefCell::new(sink),
+        }
+    }
+
+    /// Traced memory
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    /// Stop tracing and return the memory and the sink
+    pub fn into_parts(self) -> (M, S) {
+        (self.memory, self.sink.into_inner())
+    }
+
+    fn record(&self, kind: AccessKind, offset: usize, width: usize, value: u64) {
+        self.sink.borrow_mut().record(&Record {
+            timestamp: self.start.elapsed(),
+            address: self.base.wrapping_add(offset as u64),
+            width,
+            kind,
+            value,
+        });
+    }
+}
+
+macro_rules! traced_read {
+    ($($read:ident: $ty:ty),*) => {
+        $(
+            fn $read(&self, offset: usize) -> Result<$ty> {
+                let value = self.memory.$read(offset)?;
+                self.record(AccessKind::Read, offset, std::mem::size_of::<$ty>(), value as u64);
+                Ok(value)
+            }
+        )*
+    };
+}
+
+macro_rules! traced_write {
+    ($($write:ident: $ty:ty),*) => {
+        $(
+            fn $write(&mut self, offset: usize, value: $ty) -> Result<()> {
+                self.memory.$write(offset, value)?;
+                self.record(AccessKind::Write, offset, std::mem::size_of::<$ty>(), value as u64);
+                Ok(())
+            }
+        )*
+    };
+}
+
+impl<M: MemoryRead, S: TraceSink> MemoryRead for Traced<M, S> {
+    traced_read!(read_u8: u8, read_u16: u16, read_u32: u32, read_u64: u64);
+}
+
+impl<M: MemoryWrite, S: TraceSink> MemoryWrite for Traced<M, S> {
+    traced_write!(write_u8: u8, write_u16: u16, write_u32: u32, write_u64: u64);
+}
+
+/// Read whose value differs during a replay from the recorded one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub record: Record,
+    /// Value read during the replay
+    pub actual: u64,
+}
+
+/// Re-issue the recorded accesses on `memory`, whose offset 0 is at physical
+/// address `base`
+///
+/// The accesses are issued in order, without reproducing the delays between
+/// them. Returns the reads whose value differs from the recorded one. Fails
+/// with `Error::Unsupported` at the first record with a width other than 1,
+/// 2, 4 or 8 bytes.
+pub fn replay<M, I>(memory: &mut M, base: u64, records: I) -> Result<Vec<Mismatch>>
+where
+    M: MemoryWrite + ?Sized,
+    I: IntoIterator<Item = Record>,
+{
+    let mut mismatches = Vec::new();
+    for record in records {
+        let offset = record.address.checked_sub(base).ok_or(Error::OutOfBounds {
+            offset: 0,
+            len: record.width,
+        })? as usize;
+        match record.kind {
+            AccessKind::Read => {
+                let actual = match record.width {
+                    1 => u64::from(memory.read_u8(offset)?),
+                    2 => u64::from(memory.read_u16(offset)?),
+                    4 => u64::from(memory.read_u32(offset)?),
+                    8 => memory.read_u64(offset)?,
+                    _ => return Err(invalid_width(&record)),
+                };
+                if actual != record.value {
+                    mismatches.push(Mismatch { record, actual });
+                }
+            }
+            AccessKind::Write => match record.width {
+                1 => memory.write_u8(offset, record.value as u8)?,
+                2 => memory.write_u16(offset, record.value as u16)?,
+                4 => memory.write_u32(offset, record.value as u32)?,
+                8 => memory.write_u64(offset, record.value)?,
+                _ => return Err(invalid_width(&record)),
+            },
+        }
+    }
+    Ok(mismatches)
+}
+
+/// Error for a record whose width is not the width of a `Word`
+fn invalid_width(record: &Record) -> Error {
+    Error::Unsupported(format!(
+        "invalid access width of {} bytes at {:#x}, expected 1, 2, 4 or 8",
+        record.width, record.address
+    ))
+}
@@ -0,0 +1,296 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Userspace I/O (UIO) devices
+//!
+//! A UIO device exposes its memory regions as maps of `/dev/uioN`, described
+//! in `/sys/class/uio/uioN/maps`, and its interrupts as a counter that can
+//! be read from `/dev/uioN`. With the `async` feature, interrupts can also
+//! be awaited as a stream with `UioDevice::interrupts`.
+//!
+//! ```no_run
+//! use devmem::uio::UioDevice;
+//!
+//! # fn main() -> devmem::Result<()> {
+//! let device = UioDevice::find("my-fpga")?;
+//! let mut regs = unsafe { device.map(0)? };
+//! loop {
+//!     device.enable_interrupt()?;
+//!     device.wait()?;
+//!     regs.write_u32(0x0, 0x1)?;
+//! }
+//! # }
+//! ```
+
+#[cfg(feature = "async")]
+mod stream;
+
+#[cfg(feature = "async")]
+pub use stream::{InterruptEvent, InterruptStream};
+
+use crate::{Error, Mapping, Result};
+use std::convert::TryFrom;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Memory region of a UIO device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UioMap {
+    pub index: usize,
+    pub name: Option<String>,
+    /// Physical address of the first page of the region
+    pub addr: u64,
+    /// Size of the region in bytes, from the start of the first page
+    pub size: u64,
+    /// Offset of the device memory in the first page
+    pub offset: u64,
+}
+
+/// UIO device, opened for mapping its regions and waiting for interrupts
+pub struct UioDevice {
+    number: usize,
+    name: String,
+    sysfs_path: PathBuf,
+    dev_path: PathBuf,
+    file: File,
+}
+
+impl UioDevice {
+    /// Open `/dev/uioN`, where N is `number`
+    pub fn open(number: usize) -> Result<UioDevice> {
+        UioDevice::open_in("/sys", "/dev", number)
+    }
+
+    /// Open device `number` with the sysfs tree mounted at `sysfs_root` and
+    /// the device files in `dev_root`
+    pub fn open_in<P: AsRef<Path>, Q: AsRef<Path>>(
+        sysfs_root: P,
+        dev_root: Q,
+        number: usize,
+    ) -> Result<UioDevice> {
+        let sysfs_path = sysfs_root
+            .as_ref()
+            .join("class/uio")
+            .join(format!("uio{}", number));
+        let name = read_attribute(&sysfs_path.join("name"))?;
+        let dev_path = dev_root.as_ref().join(format!("uio{}", number));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&dev_path)
+            .map_err(Error::Open)?;
+        Ok(UioDevice {
+            number,
+            name,
+            sysfs_path,
+            dev_path,
+            file,
+        })
+    }
+
+    /// Open the UIO device whose driver registered it as `name`
+    pub fn find(name: &str) -> Result<UioDevice> {
+        UioDevice::find_in("/sys", "/dev", name)
+    }
+
+    /// Open the UIO device named `name` with the sysfs tree mounted at
+    /// `sysfs_root` and the device files in `dev_root`
+    pub fn find_in<P: AsRef<Path>, Q: AsRef<Path>>(
+        sysfs_root: P,
+        dev_root: Q,
+        name: &str,
+    ) -> Result<UioDevice> {
+        let number = list_in(&sysfs_root)?
+            .into_iter()
+            .find(|(_, device_name)| device_name == name)
+            .map(|(number, _)| number)
+            .ok_or_else(|| {
+                Error::Io(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no UIO device named {}", name),
+                ))
+            })?;
+        UioDevice::open_in(sysfs_root, dev_root, number)
+    }
+
+    /// Number N of `/dev/uioN`
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Name of the device
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Memory regions of the device
+    pub fn maps(&self) -> Result<Vec<UioMap>> {
+        let mut maps = Vec::new();
+        let maps_path = self.sysfs_path.join("maps");
+        if !maps_path.exists() {
+            return Ok(maps);
+        }
+        for entry in fs::read_dir(&maps_path).map_err(Error::Io)? {
+            let entry = entry.map_err(Error::Io)?;
+            let file_name = entry.file_name();
+            let index = match file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix("map"))
+                .and_then(|index| index.parse().ok())
+            {
+                Some(index) => index,
+                None => continue,
+            };
+            let path = entry.path();
+            // The name and offset attributes are not available on old
+            // kernels and for maps without a name
+            let offset = if path.join("offset").exists() {
+                read_hex_attribute(&path.join("offset"))?
+            } else {
+                0
+            };
+            maps.push(UioMap {
+                index,
+                name: read_attribute(&path.join("name")).ok(),
+                addr: read_hex_attribute(&path.join("addr"))?,
+                size: read_hex_attribute(&path.join("size"))?,
+                offset,
+            });
+        }
+        maps.sort_by_key(|map| map.index);
+        Ok(maps)
+    }
+
+    /// Map the memory region at `index`
+    ///
+    /// The mapping starts at the device memory, `offset` bytes into the
+    /// first page of the region.
+    ///
+    /// # Safety
+    ///
+    /// See `Mapping::new`.
+    pub unsafe fn map(&self, index: usize) -> Result<Mapping> {
+        let map = self
+            .maps()?
+            .into_iter()
+            .find(|map| map.index == index)
+            .ok_or_else(|| Error::Unsupported(format!("UIO map {} does not exist", index)))?;
+        if map.offset >= map.size {
+            return Err(Error::Parse(format!(
+                "UIO map {} has offset {:#x} beyond its size",
+                index, map.offset
+            )));
+        }
+
+        // The region at index N is selected by mapping /dev/uioX at an offset
+        // of N pages
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let offset = usize::try_from(map.offset).map_err(|_| Error::Overflow)?;
+        let len = usize::try_from(map.size - map.offset).map_err(|_| Error::Overflow)?;
+        let region_offset = index.checked_mul(page_size).ok_or(Error::Overflow)?;
+        Mapping::builder()
+            .device(&self.dev_path)
+            .offset(region_offset + offset)
+            .len(len)
+            .map()
+    }
+
+    /// Wait for an interrupt and return the total number of interrupts
+    /// received by the device
+    pub fn wait(&self) -> Result<u32> {
+        let mut count = [0; 4];
+        (&self.file).read_exact(&mut count).map_err(Error::Io)?;
+        Ok(u32::from_ne_bytes(count))
+    }
+
+    /// Wait for an interrupt for at most `timeout`
+    ///
+    /// Returns the total number of interrupts received by the device, or
+    /// `None` if the timeout expired.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<Option<u32>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+        let ready = loop {
+            let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+            if ready >= 0 {
+                break ready;
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(Error::Io(err));
+            }
+        };
+        if ready == 0 {
+            return Ok(None);
+        }
+        self.wait().map(Some)
+    }
+
+    /// Enable the interrupt of the device
+    ///
+    /// Drivers using the generic IRQ handling disable the interrupt when it
+    /// fires, so it must be re-enabled before waiting for the next one.
+    pub fn enable_interrupt(&self) -> Result<()> {
+        self.write_control(1)
+    }
+
+    /// Disable the interrupt of the device
+    pub fn disable_interrupt(&self) -> Result<()> {
+        self.write_control(0)
+    }
+
+    fn write_control(&self, value: u32) -> Result<()> {
+        (&self.file)
+            .write_all(&value.to_ne_bytes())
+            .map_err(Error::Io)
+    }
+}
+
+/// List the number and name of the UIO devices
+pub fn list() -> Result<Vec<(usize, String)>> {
+    list_in("/sys")
+}
+
+/// List the number and name of the UIO devices in the sysfs tree mounted at
+/// `sysfs_root`
+pub fn list_in<P: AsRef<Path>>(sysfs_root: P) -> Result<Vec<(usize, String)>> {
+    let class_path = sysfs_root.as_ref().join("class/uio");
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(&class_path).map_err(Error::Io)? {
+        let entry = entry.map_err(Error::Io)?;
+        let file_name = entry.file_name();
+        let number = match file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("uio"))
+            .and_then(|number| number.parse().ok())
+        {
+            Some(number) => number,
+            None => continue,
+        };
+        devices.push((number, read_attribute(&entry.path().join("name"))?));
+    }
+    devices.sort();
+    Ok(devices)
+}
+
+fn read_attribute(path: &Path) -> Result<String> {
+    let text = fs::read_to_string(path).map_err(Error::Io)?;
+    Ok(text.trim().to_string())
+}
+
+fn read_hex_attribute(path: &Path) -> Result<u64> {
+    let text = read_attribute(path)?;
+    let digits = text.strip_prefix("0x").unwrap_or(&text);
+    u64::from_str_radix(digits, 16)
+        .map_err(|_| Error::Parse(format!("invalid value {:?} in {}", text, path.display())))
+}
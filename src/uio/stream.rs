@@ -0,0 +1,132 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use super::UioDevice;
+use crate::{Error, Result};
+use futures_core::Stream;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+
+/// Interrupt received by an `InterruptStream`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterruptEvent {
+    /// Total number of interrupts received by the device
+    pub count: u32,
+    /// Number of interrupts received since the previous event of the stream
+    /// that were not reported, because they fired before the stream read
+    /// the counter
+    pub missed: u32,
+}
+
+/// Stream of the interrupts of a UIO device
+///
+/// The stream is driven by the tokio reactor, so it must be created and
+/// polled from within a tokio runtime.
+pub struct InterruptStream {
+    fd: AsyncFd<File>,
+    last_count: Option<u32>,
+    auto_enable: bool,
+}
+
+impl UioDevice {
+    /// Create a stream of the interrupts of the device
+    ///
+    /// The stream has its own file descriptor of the device, in non-blocking
+    /// mode, so it does not affect `wait` and `wait_timeout`.
+    pub fn interrupts(&self) -> Result<InterruptStream> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.dev_path)
+            .map_err(Error::Open)?;
+        Ok(InterruptStream {
+            fd: AsyncFd::new(file).map_err(Error::Io)?,
+            last_count: None,
+            auto_enable: false,
+        })
+    }
+}
+
+impl InterruptStream {
+    /// Enable the interrupt when the stream is created and again after each
+    /// event, for drivers that disable the interrupt when it fires
+    pub fn auto_enable(mut self, enable: bool) -> Result<InterruptStream> {
+        self.auto_enable = enable;
+        if enable {
+            self.enable_interrupt()?;
+        }
+        Ok(self)
+    }
+
+    /// Enable the interrupt of the device
+    pub fn enable_interrupt(&self) -> Result<()> {
+        self.write_control(1)
+    }
+
+    /// Disable the interrupt of the device
+    pub fn disable_interrupt(&self) -> Result<()> {
+        self.write_control(0)
+    }
+
+    fn write_control(&self, value: u32) -> Result<()> {
+        self.fd
+            .get_ref()
+            .write_all(&value.to_ne_bytes())
+            .map_err(Error::Io)
+    }
+
+    fn event(&mut self, count: u32) -> InterruptEvent {
+        let missed = match self.last_count {
+            Some(last) => count.wrapping_sub(last).wrapping_sub(1),
+            None => 0,
+        };
+        self.last_count = Some(count);
+        InterruptEvent { count, missed }
+    }
+}
+
+impl Stream for InterruptStream {
+    type Item = Result<InterruptEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let mut guard = match this.fd.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(Error::Io(err)))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let mut count = [0; 4];
+            let read = guard.try_io(|fd| match fd.get_ref().read(&mut count)? {
+                4 => Ok(()),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "short read of the interrupt count",
+                )),
+            });
+            match read {
+                Ok(Ok(())) => {
+                    let event = this.event(u32::from_ne_bytes(count));
+                    if this.auto_enable {
+                        if let Err(err) = this.enable_interrupt() {
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                    }
+                    return Poll::Ready(Some(Ok(event)));
+                }
+                Ok(Err(err)) => return Poll::Ready(Some(Err(Error::Io(err)))),
+                // The readiness was stale, wait for the next interrupt
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::region::Region;
+use crate::{register, Result};
+use std::marker::PhantomData;
+
+/// Window of a mapping borrowed for reading
+///
+/// Offsets are relative to the start of the window, and accesses outside it
+/// return `Error::OutOfBounds`.
+#[derive(Clone, Copy)]
+pub struct MappingView<'a> {
+    region: Region,
+    _mapping: PhantomData<&'a ()>,
+}
+
+impl<'a> MappingView<'a> {
+    pub(crate) fn new(region: Region) -> MappingView<'a> {
+        MappingView {
+            region,
+            _mapping: PhantomData,
+        }
+    }
+
+    /// Size of the window in bytes
+    pub fn size(&self) -> usize {
+        self.region.len()
+    }
+
+    /// Borrow `len` bytes of the window starting at `offset`
+    pub fn window(&self, offset: usize, len: usize) -> Result<MappingView<'a>> {
+        Ok(MappingView::new(self.region.window(offset, len)?))
+    }
+
+    impl_read_api!();
+
+    fn region(&self) -> Region {
+        self.region
+    }
+}
+
+/// Window of a mapping borrowed for reading and writing
+///
+/// Offsets are relative to the start of the window, and accesses outside it
+/// return `Error::OutOfBounds`. Windows obtained with `split_at_mut` are
+/// disjoint, so they can be handed to different drivers.
+pub struct MappingViewMut<'a> {
+    region: Region,
+    _mapping: PhantomData<&'a mut ()>,
+}
+
+impl<'a> MappingViewMut<'a> {
+    pub(crate) fn new(region: Region) -> MappingViewMut<'a> {
+        MappingViewMut {
+            region,
+            _mapping: PhantomData,
+        }
+    }
+
+    /// Size of the window in bytes
+    pub fn size(&self) -> usize {
+        self.region.len()
+    }
+
+    /// Borrow `len` bytes of the window starting at `offset` for reading
+    pub fn window(&self, offset: usize, len: usize) -> Result<MappingView<'_>> {
+        Ok(MappingView::new(self.region.window(offset, len)?))
+    }
+
+    /// Borrow `len` bytes of the window starting at `offset` for reading and
+    /// writing
+    pub fn window_mut(&mut self, offset: usize, len: usize) -> Result<MappingViewMut<'_>> {
+        Ok(MappingViewMut::new(self.region.window(offset, len)?))
+    }
+
+    /// Split the window into two disjoint windows, before and after `mid`
+    pub fn split_at_mut(&mut self, mid: usize) -> Result<(MappingViewMut<'_>, MappingViewMut<'_>)> {
+        let (first, second) = self.region.split_at(mid)?;
+        Ok((MappingViewMut::new(first), MappingViewMut::new(second)))
+    }
+
+    /// Split the window into two disjoint windows, consuming it so that both
+    /// keep its lifetime
+    pub fn split_into(self, mid: usize) -> Result<(MappingViewMut<'a>, MappingViewMut<'a>)> {
+        let (first, second) = self.region.split_at(mid)?;
+        Ok((MappingViewMut::new(first), MappingViewMut::new(second)))
+    }
+
+    impl_read_api!();
+    impl_write_api!();
+
+    /// Overlay the register block `T` on the start of the window
+    ///
+    /// See `Mapping::as_register_block`.
+    pub fn as_register_block<T: register::RegisterBlock>(&self) -> Result<&T> {
+        unsafe { self.region().register_block() }
+    }
+
+    fn region(&self) -> Region {
+        self.region
+    }
+}
@@ -0,0 +1,71 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{MemoryRead, MemoryWrite, Result};
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Integer types that can be accessed with a single volatile load or store
+///
+/// This trait is sealed and implemented only for `u8`, `u16`, `u32` and
+/// `u64`.
+pub trait Word: private::Sealed + Copy {
+    /// Size of the access in bytes
+    const SIZE: usize;
+
+    #[doc(hidden)]
+    fn read_from<M: MemoryRead + ?Sized>(memory: &M, offset: usize) -> Result<Self>;
+
+    #[doc(hidden)]
+    fn write_to<M: MemoryWrite + ?Sized>(self, memory: &mut M, offset: usize) -> Result<()>;
+
+    #[doc(hidden)]
+    fn from_u64(value: u64) -> Self;
+
+    #[doc(hidden)]
+    fn to_u64(self) -> u64;
+}
+
+macro_rules! impl_word {
+    ($($ty:ident => $read:ident, $write:ident;)*) => {
+        $(
+            impl private::Sealed for $ty {}
+
+            impl Word for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn read_from<M: MemoryRead + ?Sized>(memory: &M, offset: usize) -> Result<$ty> {
+                    memory.$read(offset)
+                }
+
+                fn write_to<M: MemoryWrite + ?Sized>(
+                    self,
+                    memory: &mut M,
+                    offset: usize,
+                ) -> Result<()> {
+                    memory.$write(offset, self)
+                }
+
+                fn from_u64(value: u64) -> $ty {
+                    value as $ty
+                }
+
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_word! {
+    u8 => read_u8, write_u8;
+    u16 => read_u16, write_u16;
+    u32 => read_u32, write_u32;
+    u64 => read_u64, write_u64;
+}
@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::batch::{Batch, Operation, ReadValue};
+use devmem::sim::SimMemory;
+use devmem::Error;
+
+fn memory() -> SimMemory {
+    let mut memory = SimMemory::new();
+    memory.add_region(0x0, 0x100);
+    memory
+}
+
+#[test]
+fn run_on_runs_the_operations_in_order() {
+    let mut memory = memory();
+    let mut batch = Batch::new();
+    batch
+        .write(0x1000, 0x1234_5678u32)
+        .set(0x1000, 0x8000_0000u32)
+        .clear(0x1000, 0x0000_00ffu32)
+        .read::<u32>(0x1000)
+        .write(0x1010, 0xffu8)
+        .read::<u64>(0x1010);
+    assert_eq!(
+        batch.run_on(&mut memory, 0x1000).unwrap(),
+        [
+            ReadValue {
+                address: 0x1000,
+                width: 4,
+                value: 0x9234_5600
+            },
+            ReadValue {
+                address: 0x1010,
+                width: 8,
+                value: 0xff
+            }
+        ]
+    );
+}
+
+#[test]
+fn invalid_widths_are_refused_before_running() {
+    let mut memory = memory();
+    let mut batch = Batch::new();
+    batch.write(0x1000, 0x1u32).push(Operation::Write {
+        address: 0x1008,
+        width: 3,
+        value: 0xffff_ffff_ffff_ffff,
+    });
+    assert!(matches!(
+        batch.run_on(&mut memory, 0x1000),
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(batch.mapped_ranges(), Err(Error::Unsupported(_))));
+    // Not even the valid write before it is run
+    assert!(memory.accesses().is_empty());
+    assert_eq!(memory.peek::<u64>(0x8).unwrap(), 0);
+
+    for width in [0, 16] {
+        let mut batch = Batch::new();
+        batch.push(Operation::Read {
+            address: 0x1000,
+            width,
+        });
+        assert!(matches!(
+            batch.run_on(&mut memory, 0x1000),
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,337 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::fdt::{DeviceTree, Reg};
+use devmem::Error;
+
+/// Writer of flattened device tree blobs
+#[derive(Default)]
+struct Blob {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl Blob {
+    fn begin(&mut self, name: &str) -> &mut Blob {
+        self.token(0x1);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.align();
+        self
+    }
+
+    fn end(&mut self) -> &mut Blob {
+        self.token(0x2);
+        self
+    }
+
+    fn prop(&mut self, name: &str, value: &[u8]) -> &mut Blob {
+        let name_offset = match find(&self.strings, name) {
+            Some(offset) => offset,
+            None => {
+                let offset = self.strings.len();
+                self.strings.extend_from_slice(name.as_bytes());
+                self.strings.push(0);
+                offset
+            }
+        };
+        self.token(0x3);
+        self.token(value.len() as u32);
+        self.token(name_offset as u32);
+        self.structure.extend_from_slice(value);
+        self.align();
+        self
+    }
+
+    fn cells(&mut self, name: &str, cells: &[u32]) -> &mut Blob {
+        let value: Vec<u8> = cells.iter().flat_map(|c| c.to_be_bytes()).collect();
+        self.prop(name, &value)
+    }
+
+    fn string(&mut self, name: &str, value: &str) -> &mut Blob {
+        self.prop(name, format!("{}\0", value).as_bytes())
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.token(0x9);
+        // Header, then an empty memory reservation block
+        let struct_offset = 40 + 16;
+        let strings_offset = struct_offset + self.structure.len();
+        let total_size = strings_offset + self.strings.len();
+        let header = [
+            0xd00d_feed,
+            total_size as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            40,
+            17,
+            16,
+            0,
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+        let mut blob: Vec<u8> = header.iter().flat_map(|h| h.to_be_bytes()).collect();
+        blob.extend_from_slice(&[0; 16]);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+
+    fn token(&mut self, token: u32) {
+        self.structure.extend_from_slice(&token.to_be_bytes());
+    }
+
+    fn align(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+}
+
+fn find(strings: &[u8], name: &str) -> Option<usize> {
+    let mut offset = 0;
+    for string in strings.split(|&b| b == 0) {
+        if string == name.as_bytes() {
+            return Some(offset);
+        }
+        offset += string.len() + 1;
+    }
+    None
+}
+
+/// Tree with a root using 2 address and size cells, a `soc` bus with 1
+/// cell each, mapped at 0xfe000000, and a nested legacy bus translating
+/// 0x7e000000 to 0x100000 on the `soc` bus
+fn sample() -> DeviceTree {
+    let mut blob = Blob::default();
+    blob.begin("")
+        .cells("#address-cells", &[2])
+        .cells("#size-cells", &[2])
+        .begin("aliases")
+        .string("serial0", "/soc/bus@7e000000/serial@1000")
+        .end()
+        .begin("__symbols__")
+        .string("uart0", "/soc/bus@7e000000/serial@1000")
+        .string("gpio", "/soc/gpio@200000")
+        .end()
+        .begin("memory@80000000")
+        .cells("reg", &[0x0, 0x8000_0000, 0x0, 0x4000_0000])
+        .end()
+        .begin("sram@100000000")
+        .cells("reg", &[0x1, 0x0, 0x0, 0x1_0000])
+        .end()
+        .begin("soc")
+        .string("compatible", "simple-bus")
+        .cells("#address-cells", &[1])
+        .cells("#size-cells", &[1])
+        .cells("ranges", &[0x0, 0x0, 0xfe00_0000, 0x100_0000])
+        .begin("gpio@200000")
+        .prop("compatible", b"brcm,bcm2711-gpio\0gpio\0")
+        .cells("reg", &[0x20_0000, 0x100, 0x20_1000, 0x80])
+        .end()
+        .begin("bus@7e000000")
+        .cells("#address-cells", &[1])
+        .cells("#size-cells", &[1])
+        .cells("ranges", &[0x7e00_0000, 0x10_0000, 0x1_0000])
+        .begin("serial@1000")
+        .string("compatible", "arm,pl011")
+        .cells("reg", &[0x7e00_1000, 0x200])
+        .string("status", "okay")
+        .end()
+        .begin("serial@2000")
+        .string("compatible", "arm,pl011")
+        .cells("reg", &[0x7e00_2000, 0x200])
+        .string("status", "disabled")
+        .end()
+        .begin("serial@20000")
+        .string("compatible", "arm,pl011")
+        .cells("reg", &[0x7e02_0000, 0x200])
+        .end()
+        .end()
+        .begin("i2c@300000")
+        .cells("#address-cells", &[1])
+        .cells("#size-cells", &[0])
+        .cells("reg", &[0x30_0000, 0x100])
+        .begin("eeprom@50")
+        .cells("reg", &[0x50])
+        .end()
+        .end()
+        .end()
+        .end();
+    DeviceTree::parse(&blob.finish()).unwrap()
+}
+
+#[test]
+fn reg_with_two_cells_at_the_root() {
+    let tree = sample();
+    let memory = tree.find_by_path("/memory@80000000").unwrap();
+    assert_eq!(
+        memory.reg().unwrap(),
+        [Reg {
+            address: 0x8000_0000,
+            size: 0x4000_0000
+        }]
+    );
+    let sram = tree.find_by_path("/sram").unwrap();
+    assert_eq!(
+        sram.reg().unwrap(),
+        [Reg {
+            address: 0x1_0000_0000,
+            size: 0x1_0000
+        }]
+    );
+}
+
+#[test]
+fn reg_with_one_cell_is_translated_through_ranges() {
+    let tree = sample();
+    let gpio = tree.find_by_path("/soc/gpio@200000").unwrap();
+    assert_eq!(
+        gpio.reg().unwrap(),
+        [
+            Reg {
+                address: 0xfe20_0000,
+                size: 0x100
+            },
+            Reg {
+                address: 0xfe20_1000,
+                size: 0x80
+            }
+        ]
+    );
+}
+
+#[test]
+fn nested_ranges_are_translated() {
+    let tree = sample();
+    let serial = tree.find_by_path("/soc/bus@7e000000/serial@1000").unwrap();
+    assert_eq!(
+        serial.reg().unwrap(),
+        [Reg {
+            address: 0xfe10_1000,
+            size: 0x200
+        }]
+    );
+
+    // 0x7e020000 is past the 64 KiB range of the legacy bus
+    let outside = tree.find_by_path("/soc/bus/serial@20000").unwrap();
+    match outside.reg() {
+        Err(Error::Parse(message)) => assert!(message.contains("not in the ranges")),
+        other => panic!("expected Error::Parse, got {:?}", other),
+    }
+
+    // The i2c bus has no ranges, so the addresses of its children are not
+    // physical addresses
+    let eeprom = tree.find_by_path("/soc/i2c/eeprom").unwrap();
+    match eeprom.reg() {
+        Err(Error::Parse(message)) => assert!(message.contains("no ranges property")),
+        other => panic!("expected Error::Parse, got {:?}", other),
+    }
+}
+
+#[test]
+fn find_by_label_uses_symbols_and_aliases() {
+    let tree = sample();
+    let uart = tree.find_by_label("uart0").unwrap();
+    assert_eq!(uart.path(), "/soc/bus@7e000000/serial@1000");
+    let alias = tree.find_by_label("serial0").unwrap();
+    assert_eq!(alias.path(), uart.path());
+    assert_eq!(tree.find_by_label("gpio").unwrap().name(), "gpio@200000");
+    assert!(tree.find_by_label("spi0").is_none());
+}
+
+#[test]
+fn find_by_path_needs_unit_address_only_when_ambiguous() {
+    let tree = sample();
+    assert_eq!(
+        tree.find_by_path("/soc/gpio").unwrap().path(),
+        "/soc/gpio@200000"
+    );
+    assert!(tree.find_by_path("/soc/bus/serial").is_none());
+    assert!(tree.find_by_path("/soc/missing").is_none());
+    assert_eq!(tree.find_by_path("/").unwrap().path(), "/");
+}
+
+#[test]
+fn find_compatible_and_status() {
+    let tree = sample();
+    let gpio = tree.find_compatible("gpio").unwrap();
+    assert_eq!(gpio.compatible(), ["brcm,bcm2711-gpio", "gpio"]);
+    let serials = tree.find_all_compatible("arm,pl011");
+    let enabled: Vec<bool> = serials.iter().map(|node| node.is_enabled()).collect();
+    assert_eq!(enabled, [true, false, true]);
+    assert_eq!(
+        tree.find_compatible("simple-bus")
+            .unwrap()
+            .parent()
+            .unwrap()
+            .path(),
+        "/"
+    );
+}
+
+#[test]
+fn map_refuses_regions_without_size() {
+    let tree = sample();
+    let eeprom = tree.find_by_path("/soc/i2c/eeprom").unwrap();
+    let i2c = eeprom.parent().unwrap();
+    assert_eq!(
+        i2c.reg().unwrap(),
+        [Reg {
+            address: 0xfe30_0000,
+            size: 0x100
+        }]
+    );
+    // A node with a zero size, on a bus with ranges
+    let mut blob = Blob::default();
+    blob.begin("")
+        .cells("#address-cells", &[1])
+        .cells("#size-cells", &[0])
+        .begin("cpu@1")
+        .cells("reg", &[0x1])
+        .end()
+        .end();
+    let tree = DeviceTree::parse(&blob.finish()).unwrap();
+    let cpu = tree.find_by_path("/cpu@1").unwrap();
+    assert_eq!(
+        cpu.reg().unwrap(),
+        [Reg {
+            address: 1,
+            size: 0
+        }]
+    );
+    assert!(matches!(unsafe { cpu.map(0) }, Err(Error::ZeroLength)));
+}
+
+#[test]
+fn parse_refuses_malformed_blobs() {
+    let mut blob = Blob::default();
+    blob.begin("").begin("soc").end().end();
+    let valid = blob.finish();
+    assert!(DeviceTree::parse(&valid).is_ok());
+
+    let mut bad_magic = valid.clone();
+    bad_magic[0] = 0;
+    assert!(matches!(
+        DeviceTree::parse(&bad_magic),
+        Err(Error::Parse(_))
+    ));
+    assert!(matches!(
+        DeviceTree::parse(&valid[..valid.len() - 4]),
+        Err(Error::Parse(_))
+    ));
+    assert!(matches!(
+        DeviceTree::parse(&valid[..20]),
+        Err(Error::Parse(_))
+    ));
+
+    let mut unbalanced = Blob::default();
+    unbalanced.begin("").begin("soc").end();
+    assert!(matches!(
+        DeviceTree::parse(&unbalanced.finish()),
+        Err(Error::Parse(_))
+    ));
+}
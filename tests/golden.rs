@@ -0,0 +1,74 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::golden::{Golden, Mismatch};
+use devmem::sim::Access;
+
+#[test]
+fn wildcards_do_not_take_accesses_of_specific_expectations() {
+    let golden = Golden::parse("{\nwrite 0x10 u32 *\nwrite 0x10 u32 0x00000005\n}").unwrap();
+    let accesses = [Access::write(0x10, 5u32), Access::write(0x10, 7u32)];
+    assert_eq!(golden.compare(&accesses), None);
+    let accesses = [Access::write(0x10, 7u32), Access::write(0x10, 5u32)];
+    assert_eq!(golden.compare(&accesses), None);
+}
+
+#[test]
+fn unordered_group_reports_the_first_unmatched_access() {
+    let golden = Golden::parse(
+        "read 0x0 u32 *\n{\nwrite 0x10 u32 *\nwrite 0x10 u32 0x00000005\nwrite 0x14 u8 0x1x\n}\nread 0x0 u32 0x00000000",
+    )
+    .unwrap();
+
+    let accesses = [
+        Access::read(0x0, 0u32),
+        Access::write(0x14, 0x12u8),
+        Access::write(0x10, 7u32),
+        Access::write(0x10, 8u32),
+        Access::read(0x0, 0u32),
+    ];
+    assert_eq!(
+        golden.compare(&accesses),
+        Some(Mismatch {
+            index: 3,
+            expected: Some("write 0x10 u32 0x00000005".to_string()),
+            actual: Some(Access::write(0x10, 8u32)),
+        })
+    );
+
+    // The group must be complete before the next entry
+    let accesses = [
+        Access::read(0x0, 0u32),
+        Access::write(0x10, 5u32),
+        Access::read(0x0, 0u32),
+        Access::write(0x14, 0x12u8),
+    ];
+    assert_eq!(golden.compare(&accesses).unwrap().index, 2);
+
+    let accesses = [Access::read(0x0, 0u32), Access::write(0x10, 5u32)];
+    assert_eq!(
+        golden.compare(&accesses),
+        Some(Mismatch {
+            index: 2,
+            expected: Some("write 0x10 u32 * or write 0x14 u8 0x1x".to_string()),
+            actual: None,
+        })
+    );
+}
+
+#[test]
+fn extra_accesses_are_reported() {
+    let golden = Golden::parse("write 0x0 u16 0x0001").unwrap();
+    let accesses = [Access::write(0x0, 1u16), Access::read(0x2, 0u16)];
+    assert_eq!(
+        golden.compare(&accesses),
+        Some(Mismatch {
+            index: 1,
+            expected: None,
+            actual: Some(Access::read(0x2, 0u16)),
+        })
+    );
+}
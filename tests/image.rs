@@ -0,0 +1,156 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::image::{self, ParseError, Segment};
+
+fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7 + 3) as u8).collect()
+}
+
+fn ihex(address: usize, data: &[u8]) -> String {
+    let mut out = Vec::new();
+    image::write_ihex(&mut out, address, data).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn srec(address: usize, data: &[u8]) -> String {
+    let mut out = Vec::new();
+    image::write_srec(&mut out, address, data).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn ihex_round_trip_across_64k_boundary() {
+    let data = pattern(0x40);
+    let text = ihex(0x1_ffe8, &data);
+
+    let extended: Vec<&str> = text
+        .lines()
+        .filter(|l| l.starts_with(":02000004"))
+        .collect();
+    assert_eq!(extended, [":020000040001F9", ":020000040002F8"]);
+    // The record before the boundary is cut short, so that no record
+    // crosses it
+    assert!(text.lines().any(|l| l.starts_with(":08FFF800")));
+    assert!(text.ends_with(":00000001FF\n"));
+
+    let segments = image::parse_ihex(&text).unwrap();
+    assert_eq!(
+        segments,
+        [Segment {
+            address: 0x1_ffe8,
+            data
+        }]
+    );
+}
+
+#[test]
+fn srec_uses_the_smallest_address_size() {
+    let cases: [(usize, usize, &str, &str); 4] = [
+        (0xfff0, 0x10, "S1", "S9"),
+        (0xfff0, 0x11, "S2", "S8"),
+        (0xff_fff0, 0x10, "S2", "S8"),
+        (0xff_fff0, 0x11, "S3", "S7"),
+    ];
+    for &(address, len, data_kind, end_kind) in &cases {
+        let data = pattern(len);
+        let text = srec(address, &data);
+        let kinds: Vec<&str> = text.lines().map(|line| &line[..2]).collect();
+        assert_eq!(kinds[0], "S0");
+        assert!(kinds[1..kinds.len() - 2].iter().all(|&k| k == data_kind));
+        assert_eq!(kinds[kinds.len() - 2], "S5");
+        assert_eq!(kinds[kinds.len() - 1], end_kind);
+        assert_eq!(
+            image::parse_srec(&text).unwrap(),
+            [Segment { address, data }],
+            "{} at {:#x}",
+            data_kind,
+            address
+        );
+    }
+}
+
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn images_cannot_address_more_than_4_gib() {
+    assert!(image::write_ihex(Vec::new(), 0xffff_fff0, &[0; 0x11]).is_err());
+    assert!(image::write_srec(Vec::new(), 0xffff_fff0, &[0; 0x11]).is_err());
+    assert!(image::write_srec(Vec::new(), 0xffff_fff0, &[0; 0x10]).is_ok());
+}
+
+#[test]
+fn checksum_and_length_errors_report_the_line() {
+    let error = |line: usize, message: &str| {
+        Err(ParseError {
+            line,
+            message: message.to_string(),
+        })
+    };
+
+    let text = ":0400100001020304E2\n:0400140001020304DF\n";
+    assert_eq!(image::parse_ihex(text), error(2, "checksum mismatch"));
+    let text = ":0400100001020304E2\n\n:0500100001020304E1\n";
+    assert_eq!(
+        image::parse_ihex(text),
+        error(3, "record length does not match the byte count")
+    );
+    assert_eq!(
+        image::parse_ihex("0400100001020304E2"),
+        error(1, "missing ':' at the start of the record")
+    );
+
+    let text = "S107001001020304DE\nS107001401020304DB\n";
+    assert_eq!(image::parse_srec(text), error(2, "checksum mismatch"));
+    let text = "S107001001020304DE\nS10800100102030400\n";
+    assert_eq!(
+        image::parse_srec(text),
+        error(2, "record length does not match the byte count")
+    );
+    assert_eq!(
+        image::parse_srec("S4030000FC"),
+        error(1, "unknown record type")
+    );
+}
+
+#[test]
+fn overlapping_records_are_merged_by_address() {
+    // 0x10..0x14 = 01 02 03 04, then 0x12..0x16 = aa bb cc dd, then
+    // 0x10..0x12 = ee ff
+    let text = "\
+:0400100001020304E2
+:04001200AABBCCDDDC
+:02001000EEFF01
+:00000001FF
+";
+    assert_eq!(
+        image::parse_ihex(text).unwrap(),
+        [Segment {
+            address: 0x10,
+            data: vec![0xee, 0xff, 0xaa, 0xbb, 0xcc, 0xdd]
+        }]
+    );
+
+    // A record contained in an earlier one starting at a lower address
+    // overwrites its bytes, and disjoint records stay separate segments
+    let text = "\
+S107001001020304DE
+S1050011AABB84
+S10500201122A7
+";
+    assert_eq!(
+        image::parse_srec(text).unwrap(),
+        [
+            Segment {
+                address: 0x10,
+                data: vec![0x01, 0xaa, 0xbb, 0x04]
+            },
+            Segment {
+                address: 0x20,
+                data: vec![0x11, 0x22]
+            }
+        ]
+    );
+}
@@ -0,0 +1,177 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::iomem::{Iomem, Issue};
+use devmem::{Error, Mapping};
+
+fn fixture() -> Iomem {
+    Iomem::load_from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/iomem/iomem")).unwrap()
+}
+
+fn parse_error(text: &str) -> String {
+    match Iomem::parse(text) {
+        Err(Error::Parse(message)) => message,
+        other => panic!("expected Error::Parse, got {:?}", other),
+    }
+}
+
+#[test]
+fn parse_builds_the_resource_tree() {
+    let iomem = fixture();
+    let names: Vec<&str> = iomem.resources().iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "Reserved",
+            "System RAM",
+            "PCI Bus 0000:00",
+            "System ROM",
+            "System RAM",
+            "PCI Bus 0000:00",
+            "fe200000.gpio",
+            "fe215040.serial"
+        ]
+    );
+
+    let ram = &iomem.resources()[4];
+    assert_eq!((ram.start, ram.end), (0x10_0000, 0x3fff_ffff));
+    assert!(ram.is_system_ram());
+    assert_eq!(ram.children.len(), 2);
+    assert_eq!(ram.children[1].name, "Kernel data");
+
+    let bus = &iomem.resources()[5];
+    assert_eq!(bus.children.len(), 2);
+    assert_eq!(bus.children[0].children[0].name, "vga");
+    assert_eq!(bus.children[1].children[0].name, "e1000");
+    assert_eq!(bus.size(), 0x1000_0000);
+}
+
+#[test]
+fn parse_reports_malformed_lines() {
+    assert_eq!(
+        parse_error("00000000-00000fff : Reserved\n00001000-0009ffff System RAM\n"),
+        "iomem line 2: missing ' : ' separator"
+    );
+    assert_eq!(
+        parse_error("00001000 : System RAM"),
+        "iomem line 1: missing '-' in the address range"
+    );
+    assert_eq!(
+        parse_error("0000g000-0009ffff : System RAM"),
+        "iomem line 1: invalid start address"
+    );
+    assert_eq!(
+        parse_error("00001000-0009fffz : System RAM"),
+        "iomem line 1: invalid end address"
+    );
+    assert_eq!(
+        parse_error("00002000-00001000 : System RAM"),
+        "iomem line 1: end address before start address"
+    );
+    assert_eq!(
+        parse_error("00000000-00000fff : Reserved\n    00000000-000000ff : child\n"),
+        "iomem line 2: resource nested without a parent"
+    );
+}
+
+#[test]
+fn find_searches_all_depths_in_order() {
+    let iomem = fixture();
+    let serial = iomem.find("serial").unwrap();
+    assert_eq!((serial.start, serial.end), (0xfe21_5040, 0xfe21_507f));
+    assert_eq!(iomem.find("e1000").unwrap().start, 0xe100_0000);
+    // Depth-first order: the parent comes before its children
+    assert_eq!(iomem.find("0000:00:0").unwrap().name, "0000:00:02.0");
+    let found: Vec<&str> = iomem
+        .find_all("PCI Bus")
+        .iter()
+        .map(|r| r.name.as_str())
+        .collect();
+    assert_eq!(found, ["PCI Bus 0000:00", "PCI Bus 0000:00"]);
+    assert!(iomem.find("missing").is_none());
+}
+
+#[test]
+fn size_of_the_whole_address_space() {
+    let iomem = Iomem::parse("0000000000000000-ffffffffffffffff : PCI mem").unwrap();
+    assert_eq!(iomem.resources()[0].size(), 1 << 64);
+}
+
+#[test]
+fn check_accepts_ranges_inside_a_device() {
+    let iomem = fixture();
+    assert_eq!(iomem.check(0xfe21_5040, 0x40), []);
+    assert_eq!(iomem.check(0xe100_0000, 0x1000), []);
+    // A range covering whole resources is accepted too
+    assert_eq!(iomem.check(0xe000_0000, 0x100_0000), []);
+    assert_eq!(iomem.check(0xfe21_5040, 0), []);
+    assert!(iomem.validate(0xfe20_0000, 0x100).is_ok());
+}
+
+#[test]
+fn check_reports_system_ram() {
+    let issues = fixture().check(0x2000, 0x1000);
+    assert_eq!(
+        issues,
+        [Issue::SystemRam {
+            start: 0x1000,
+            end: 0x9_ffff
+        }]
+    );
+}
+
+#[test]
+fn check_reports_crossed_boundaries() {
+    let issues = fixture().check(0xe07f_f000, 0x2000);
+    assert_eq!(
+        issues,
+        [Issue::CrossesBoundary {
+            name: "vga".to_string(),
+            start: 0xe000_0000,
+            end: 0xe07f_ffff
+        }]
+    );
+}
+
+#[test]
+fn check_reports_unclaimed_ranges() {
+    // The GPIO block is followed by a hole
+    assert_eq!(
+        fixture().check(0xfe20_0000, 0x1_0000),
+        [Issue::Unclaimed {
+            start: 0xfe20_0100,
+            end: 0xfe20_ffff
+        }]
+    );
+    assert_eq!(
+        fixture().check(0x4000_0000, 0x1000),
+        [Issue::Unclaimed {
+            start: 0x4000_0000,
+            end: 0x4000_0fff
+        }]
+    );
+}
+
+#[test]
+fn check_reports_hidden_addresses() {
+    let iomem = Iomem::parse(
+        "00000000-00000000 : Reserved\n00000000-00000000 : System RAM\n  00000000-00000000 : Kernel code\n",
+    )
+    .unwrap();
+    assert_eq!(iomem.check(0x1000, 0x1000), [Issue::AddressesHidden]);
+}
+
+#[test]
+fn builder_refuses_ranges_with_issues() {
+    let result = unsafe {
+        Mapping::builder()
+            .offset(0x2000)
+            .len(0x1000)
+            .iomem(fixture())
+            .map()
+    };
+    assert!(matches!(result, Err(Error::Iomem(Issue::SystemRam { .. }))));
+}
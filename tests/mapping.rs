@@ -0,0 +1,139 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::{Error, Mapping};
+use std::fs;
+use std::io;
+use tempfile::NamedTempFile;
+
+/// Temporary file of `len` zero bytes
+fn zeroed_file(len: u64) -> NamedTempFile {
+    let file = NamedTempFile::new().unwrap();
+    file.as_file().set_len(len).unwrap();
+    file
+}
+
+#[test]
+fn builder_maps_file_at_unaligned_offset() {
+    let file = zeroed_file(0x3000);
+    let mut mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .offset(0x1ff0)
+            .len(0x20)
+            .map()
+            .unwrap()
+    };
+    mapping.write_u32(0x0, 0xdead_beef).unwrap();
+    mapping.write_u8(0x10, 0x5a).unwrap();
+    mapping.copy_from_slice(&[0xaa; 4][..]);
+    assert_eq!(mapping.read_u32(0x0).unwrap(), 0xaaaa_aaaa);
+    drop(mapping);
+
+    let data = fs::read(file.path()).unwrap();
+    assert_eq!(&data[0x1ff0..0x1ff4], &[0xaa; 4]);
+    assert_eq!(data[0x2000], 0x5a);
+    assert!(data[..0x1ff0].iter().all(|&byte| byte == 0));
+    assert!(data[0x2001..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn missing_device_fails_to_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = unsafe {
+        Mapping::builder()
+            .device(dir.path().join("missing"))
+            .len(0x10)
+            .map()
+    };
+    match result {
+        Err(Error::Open(err)) => assert_eq!(err.kind(), io::ErrorKind::NotFound),
+        other => panic!("expected Error::Open, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn range_past_the_address_space_overflows() {
+    let file = zeroed_file(0x1000);
+    let result = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .offset(usize::MAX - 0x8)
+            .len(0x10)
+            .map()
+    };
+    assert!(matches!(result, Err(Error::Overflow)));
+}
+
+#[test]
+fn zero_length_is_refused() {
+    let file = zeroed_file(0x1000);
+    let result = unsafe { Mapping::builder().device(file.path()).map() };
+    assert!(matches!(result, Err(Error::ZeroLength)));
+}
+
+#[test]
+fn directory_cannot_be_mapped() {
+    // A directory can be opened read-only, but not mmapped
+    let dir = tempfile::tempdir().unwrap();
+    let result = unsafe {
+        Mapping::builder()
+            .device(dir.path())
+            .len(0x10)
+            .map_readonly()
+    };
+    assert!(matches!(result, Err(Error::Mmap(_))));
+}
+
+#[test]
+fn misaligned_access_is_refused() {
+    let file = zeroed_file(0x1000);
+    let mut mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(0x10)
+            .map()
+            .unwrap()
+    };
+    assert!(matches!(
+        mapping.read_u32(0x2),
+        Err(Error::Misaligned {
+            offset: 0x2,
+            align: 4
+        })
+    ));
+    assert!(matches!(
+        mapping.write_u64(0x4, 0),
+        Err(Error::Misaligned {
+            offset: 0x4,
+            align: 8
+        })
+    ));
+}
+
+#[test]
+fn access_past_the_end_is_out_of_bounds() {
+    let file = zeroed_file(0x1000);
+    let mut mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(0x10)
+            .map()
+            .unwrap()
+    };
+    assert!(mapping.read_u64(0x8).is_ok());
+    assert!(matches!(
+        mapping.read_u32(0x10),
+        Err(Error::OutOfBounds {
+            offset: 0x10,
+            len: 4
+        })
+    ));
+    assert!(matches!(
+        mapping.write_u8(usize::MAX, 0),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
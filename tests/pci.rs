@@ -0,0 +1,157 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::pci::{Bar, PciDevice};
+use devmem::Error;
+use std::fs::{self, File};
+use tempfile::TempDir;
+
+const ADDRESS: &str = "0000:03:00.0";
+
+/// `resource` file of a device with a 64-bit prefetchable BAR 0, an I/O BAR
+/// 2 and a 32-bit BAR 3, followed by the ROM and bridge windows
+const RESOURCE: &str = "\
+0x00000000fe000000 0x00000000fe00ffff 0x000000000014220c
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x000000000000e000 0x000000000000e01f 0x0000000000040101
+0x00000000fd000000 0x00000000fd000fff 0x0000000000040200
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x0000000000000000 0x0000000000000000 0x0000000000000000
+0x00000000fd100000 0x00000000fd17ffff 0x0000000000046200
+";
+
+/// Temporary sysfs tree with the device at `ADDRESS`
+fn sysfs() -> TempDir {
+    let root = TempDir::new().unwrap();
+    let device = root.path().join("bus/pci/devices").join(ADDRESS);
+    fs::create_dir_all(&device).unwrap();
+    fs::write(device.join("resource"), RESOURCE).unwrap();
+    fs::write(device.join("enable"), "0\n").unwrap();
+    let bar0 = File::create(device.join("resource0")).unwrap();
+    bar0.set_len(0x1_0000).unwrap();
+    root
+}
+
+#[test]
+fn bars_skips_unimplemented_and_upper_halves() {
+    let root = sysfs();
+    let device = PciDevice::open_in(root.path(), ADDRESS).unwrap();
+    assert_eq!(device.address(), ADDRESS);
+    let bars = device.bars().unwrap();
+    assert_eq!(
+        bars,
+        [
+            Bar {
+                index: 0,
+                start: 0xfe00_0000,
+                size: 0x1_0000,
+                flags: 0x14_220c
+            },
+            Bar {
+                index: 2,
+                start: 0xe000,
+                size: 0x20,
+                flags: 0x4_0101
+            },
+            Bar {
+                index: 3,
+                start: 0xfd00_0000,
+                size: 0x1000,
+                flags: 0x4_0200
+            }
+        ]
+    );
+
+    let flags: Vec<(bool, bool, bool, bool)> = bars
+        .iter()
+        .map(|bar| {
+            (
+                bar.is_memory(),
+                bar.is_io(),
+                bar.is_prefetchable(),
+                bar.is_64bit(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        flags,
+        [
+            (true, false, true, true),
+            (false, true, false, false),
+            (true, false, false, false)
+        ]
+    );
+    assert!(matches!(device.bar(1), Err(Error::Unsupported(_))));
+}
+
+#[test]
+fn bars_refuses_malformed_resource_file() {
+    let root = sysfs();
+    let device = PciDevice::open_in(root.path(), ADDRESS).unwrap();
+    fs::write(device.path().join("resource"), "0xfe000000 0xfe00ffff\n").unwrap();
+    assert!(matches!(device.bars(), Err(Error::Parse(_))));
+    fs::write(
+        device.path().join("resource"),
+        "0xfe000000 0xfe00ffff 0xzz\n",
+    )
+    .unwrap();
+    assert!(matches!(device.bars(), Err(Error::Parse(_))));
+}
+
+#[test]
+fn open_in_requires_the_resource_file() {
+    let root = sysfs();
+    assert!(matches!(
+        PciDevice::open_in(root.path(), "0000:04:00.0"),
+        Err(Error::Io(_))
+    ));
+}
+
+#[test]
+fn enable_writes_the_enable_file() {
+    let root = sysfs();
+    let device = PciDevice::open_in(root.path(), ADDRESS).unwrap();
+    assert!(!device.is_enabled().unwrap());
+    device.enable().unwrap();
+    assert_eq!(
+        fs::read_to_string(device.path().join("enable")).unwrap(),
+        "1"
+    );
+    assert!(device.is_enabled().unwrap());
+}
+
+#[test]
+fn map_bar_maps_the_resource_file() {
+    let root = sysfs();
+    let device = PciDevice::open_in(root.path(), ADDRESS).unwrap();
+    let mut bar0 = unsafe { device.map_bar(0).unwrap() };
+    assert!(matches!(
+        bar0.read_u8(0x1_0000),
+        Err(Error::OutOfBounds { .. })
+    ));
+    bar0.write_u32(0xfffc, 0x1234_5678).unwrap();
+    assert_eq!(bar0.read_u32(0xfffc).unwrap(), 0x1234_5678);
+    drop(bar0);
+
+    let data = fs::read(device.resource_path(0)).unwrap();
+    assert_eq!(&data[0xfffc..], &0x1234_5678u32.to_ne_bytes());
+
+    // I/O and unimplemented BARs cannot be mapped, nor BARs without a
+    // write-combining resource file with write-combining
+    assert!(matches!(
+        unsafe { device.map_bar(2) },
+        Err(Error::Unsupported(_))
+    ));
+    assert!(matches!(
+        unsafe { device.map_bar(1) },
+        Err(Error::Unsupported(_))
+    ));
+    assert!(device.resource_wc_path(0).is_none());
+    assert!(matches!(
+        unsafe { device.map_bar_wc(0) },
+        Err(Error::Unsupported(_))
+    ));
+}
@@ -0,0 +1,71 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::batch::Batch;
+use devmem::poll::Poller;
+use devmem::sim::SimMemory;
+use devmem::Error;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_millis(5);
+
+fn memory(status: u32) -> SimMemory {
+    let mut memory = SimMemory::new();
+    memory.add_region(0x0, 0x100);
+    memory.poke(0x8, status).unwrap();
+    memory
+}
+
+#[test]
+fn poll_until_compares_only_the_masked_bits() {
+    let memory = memory(0x8000_0031);
+    let poller = Poller::new(TIMEOUT);
+    assert_eq!(
+        poller.poll_until(&memory, 0x8, 0x0fu32, 0x1).unwrap(),
+        0x8000_0031
+    );
+    // The bits of the value outside the mask are ignored
+    assert_eq!(
+        poller
+            .poll_until(&memory, 0x8, 0x0fu32, 0xffff_fff1)
+            .unwrap(),
+        0x8000_0031
+    );
+    assert!(matches!(
+        poller.poll_until(&memory, 0x8, 0x0fu32, 0x2),
+        Err(Error::Timeout { last: 0x8000_0031 })
+    ));
+}
+
+#[test]
+fn batch_poll_compares_only_the_masked_bits() {
+    let mut memory = memory(0x8000_0031);
+    let mut batch = Batch::new();
+    batch.poll(0x1008, 0x8000_0000u32, 0xffff_ffff, TIMEOUT);
+    assert!(batch.run_on(&mut memory, 0x1000).is_ok());
+
+    let mut batch = Batch::new();
+    batch.poll(0x1008, 0x8000_0000u32, 0x0, TIMEOUT);
+    assert!(matches!(
+        batch.run_on(&mut memory, 0x1000),
+        Err(Error::Timeout { last: 0x8000_0031 })
+    ));
+}
+
+#[test]
+fn long_intervals_do_not_overflow() {
+    let memory = memory(0x0);
+    let poller = Poller::new(Duration::from_millis(1)).interval(Duration::MAX);
+    assert!(matches!(
+        poller.poll_until(&memory, 0x8, 0x1u32, 0x1),
+        Err(Error::Timeout { last: 0 })
+    ));
+    let poller = Poller::new(Duration::from_millis(1)).backoff(Duration::MAX / 2, Duration::MAX);
+    assert!(matches!(
+        poller.poll_until(&memory, 0x8, 0x1u32, 0x1),
+        Err(Error::Timeout { last: 0 })
+    ));
+}
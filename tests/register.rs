@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::register::{ReadOnly, ReadWrite, RegisterBlock, WriteOnly};
+use devmem::{Error, Mapping};
+use std::fs;
+use tempfile::NamedTempFile;
+
+#[repr(C)]
+struct Uart {
+    data: ReadWrite<u32>,
+    status: ReadOnly<u32>,
+    control: WriteOnly<u32>,
+    _reserved: ReadOnly<u32>,
+    baud: ReadWrite<u64>,
+}
+
+unsafe impl RegisterBlock for Uart {}
+
+fn mapped_file(len: usize) -> (NamedTempFile, Mapping) {
+    let file = NamedTempFile::new().unwrap();
+    file.as_file().set_len(0x1000).unwrap();
+    let mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(len)
+            .map()
+            .unwrap()
+    };
+    (file, mapping)
+}
+
+#[test]
+fn register_block_round_trips_through_the_file() {
+    let (file, mut mapping) = mapped_file(0x100);
+    mapping.write_u32(0x4, 0x1).unwrap();
+    {
+        let uart = mapping.as_register_block::<Uart>().unwrap();
+        assert_eq!(uart.status.read(), 0x1);
+        uart.data.write(0x55);
+        uart.data.modify(|data| data | 0x100);
+        uart.control.write(0x3);
+        uart.baud.write(115_200);
+        uart.baud.modify(|baud| baud * 2);
+        assert_eq!(uart.data.read(), 0x155);
+    }
+    drop(mapping);
+
+    let data = fs::read(file.path()).unwrap();
+    assert_eq!(&data[0x0..0x4], &0x155u32.to_ne_bytes());
+    assert_eq!(&data[0x8..0xc], &0x3u32.to_ne_bytes());
+    assert_eq!(&data[0x10..0x18], &230_400u64.to_ne_bytes());
+}
+
+#[test]
+fn register_block_must_fit_in_the_mapping() {
+    let (_file, mapping) = mapped_file(0x17);
+    assert!(matches!(
+        mapping.as_register_block::<Uart>(),
+        Err(Error::OutOfBounds {
+            offset: 0,
+            len: 0x18
+        })
+    ));
+
+    let (_file, mut mapping) = mapped_file(0x100);
+    let window = mapping.window_mut(0xf0, 0x10).unwrap();
+    assert!(matches!(
+        window.as_register_block::<Uart>(),
+        Err(Error::OutOfBounds {
+            offset: 0,
+            len: 0x18
+        })
+    ));
+}
+
+#[test]
+fn register_block_must_be_aligned() {
+    let (_file, mut mapping) = mapped_file(0x100);
+    let window = mapping.window_mut(0x2, 0x20).unwrap();
+    match window.as_register_block::<Uart>() {
+        Err(Error::Misaligned { offset: 0, align }) => {
+            assert_eq!(align, std::mem::align_of::<Uart>())
+        }
+        other => panic!("expected Error::Misaligned, got {:?}", other.err()),
+    }
+    let window = mapping.window_mut(0x8, 0x20).unwrap();
+    assert!(window.as_register_block::<Uart>().is_ok());
+}
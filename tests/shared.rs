@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::{Error, Mapping, SharedMapping};
+use std::thread;
+use tempfile::NamedTempFile;
+
+fn shared_file(len: u64) -> (NamedTempFile, SharedMapping) {
+    let file = NamedTempFile::new().unwrap();
+    file.as_file().set_len(len).unwrap();
+    let mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(len as usize)
+            .map()
+            .unwrap()
+    };
+    (file, SharedMapping::new(mapping))
+}
+
+/// Values whose halves are equal, so that a torn read is detected
+fn pattern(writer: u64, index: u64) -> u64 {
+    let half = (writer << 24) | index;
+    (half << 32) | half
+}
+
+// Checks the single-copy atomicity of aligned volatile accesses provided by
+// the hardware, which is what device registers rely on. `u64` accesses may be
+// split on 32-bit architectures.
+#[cfg(target_pointer_width = "64")]
+#[test]
+fn concurrent_accesses_to_the_same_word_do_not_tear() {
+    const WRITERS: u64 = 4;
+    const WRITES: u64 = 10_000;
+    let (_file, shared) = shared_file(0x1000);
+    shared.write_u64(0x8, pattern(0, 0)).unwrap();
+
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|writer| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for index in 0..WRITES {
+                    shared.write_u64(0x8, pattern(writer, index)).unwrap();
+                    shared
+                        .write_u32(0x10, pattern(writer, index) as u32)
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    let readers: Vec<_> = (0..WRITERS)
+        .map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..WRITES {
+                    let value = shared.read_u64(0x8).unwrap();
+                    assert_eq!(value >> 32, value & 0xffff_ffff, "torn read {:#x}", value);
+                    let value = shared.read_u32(0x10).unwrap();
+                    assert!(u64::from(value >> 24) < WRITERS);
+                    assert!(u64::from(value & 0xff_ffff) < WRITES);
+                }
+            })
+        })
+        .collect();
+    for thread in writers.into_iter().chain(readers) {
+        thread.join().unwrap();
+    }
+
+    // The last write of one of the writers wins
+    let value = shared.read_u64(0x8).unwrap();
+    assert!((0..WRITERS).any(|writer| value == pattern(writer, WRITES - 1)));
+}
+
+#[test]
+fn accesses_are_checked() {
+    let (_file, shared) = shared_file(0x1000);
+    assert!(matches!(
+        shared.read_u32(0x2),
+        Err(Error::Misaligned {
+            offset: 0x2,
+            align: 4
+        })
+    ));
+    assert!(matches!(
+        shared.write_u64(0x1000, 0),
+        Err(Error::OutOfBounds { .. })
+    ));
+}
@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::sim::SimMemory;
+use devmem::{Error, Mapping, MemoryRead, MemoryWrite};
+use tempfile::NamedTempFile;
+
+/// Check that `SimMemory` and a `Mapping` return the same error for an
+/// access of a `u32` at `offset`
+fn same_error(memory: &mut SimMemory, mapping: &mut Mapping, offset: usize) -> Error {
+    let (sim, real) = (memory.read_u32(offset), mapping.read_u32(offset));
+    let error = match (sim, real) {
+        (Err(sim), Err(real)) => {
+            assert_eq!(format!("{:?}", sim), format!("{:?}", real));
+            sim
+        }
+        other => panic!("expected two errors, got {:?}", other),
+    };
+    assert_eq!(
+        format!("{:?}", memory.write_u32(offset, 0).unwrap_err()),
+        format!("{:?}", error)
+    );
+    error
+}
+
+#[test]
+fn errors_match_a_mapping() {
+    let file = NamedTempFile::new().unwrap();
+    file.as_file().set_len(0x1000).unwrap();
+    let mut mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(0x1000)
+            .map()
+            .unwrap()
+    };
+    let mut memory = SimMemory::new();
+    memory.add_region(0x0, 0x1000);
+
+    assert!(matches!(
+        same_error(&mut memory, &mut mapping, 0x1000),
+        Error::OutOfBounds {
+            offset: 0x1000,
+            len: 4
+        }
+    ));
+    assert!(matches!(
+        same_error(&mut memory, &mut mapping, usize::MAX - 3),
+        Error::OutOfBounds { len: 4, .. }
+    ));
+    assert!(matches!(
+        same_error(&mut memory, &mut mapping, 0x2),
+        Error::Misaligned {
+            offset: 0x2,
+            align: 4
+        }
+    ));
+}
@@ -0,0 +1,70 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::sim::SimMemory;
+
+// The expected output of the generator for svd/sample.svd, compiled as part
+// of the test
+#[allow(dead_code)]
+mod sample {
+    include!("svd/sample.rs");
+}
+
+#[cfg(feature = "svd")]
+#[test]
+fn sample_generates_expected_code() {
+    let code = devmem::svd::generate(include_str!("svd/sample.svd")).unwrap();
+    assert_eq!(code, include_str!("svd/sample.rs"));
+}
+
+#[test]
+fn generated_registers_access_memory() {
+    use sample::uart1;
+
+    let mut memory = SimMemory::new();
+    memory.add_region(0x0, uart1::SIZE);
+    assert_eq!(uart1::BASE_ADDRESS, 0x4000_2000);
+
+    uart1::ctrl::write(&mut memory, |w| w.mode(uart1::ctrl::Mode::Rx).en(1)).unwrap();
+    assert_eq!(memory.peek::<u32>(0x0).unwrap(), 0x105);
+    let ctrl = uart1::ctrl::read(&memory).unwrap();
+    assert_eq!(ctrl.mode(), Some(uart1::ctrl::Mode::Rx));
+    assert_eq!(ctrl.div(), 0x1);
+
+    uart1::data3::write(&mut memory, |w| w.bits(0xabcd)).unwrap();
+    assert_eq!(memory.peek::<u32>(0x1c).unwrap(), 0xabcd);
+    memory.poke(0x50, 0x7u8).unwrap();
+    assert_eq!(uart1::chb_cfg::read(&memory).unwrap().bits(), 0x7);
+    memory.poke(0x4, 0x1u16).unwrap();
+    assert_eq!(uart1::status::read(&memory).unwrap().rxne(), 1);
+}
+
+#[cfg(feature = "svd")]
+#[test]
+fn invalid_bit_ranges_are_refused() {
+    let svd = |field: &str| {
+        format!(
+            "<device><name>D</name><peripherals><peripheral><name>P</name>\
+             <baseAddress>0x0</baseAddress><registers><register><name>R</name>\
+             <addressOffset>0x0</addressOffset><size>16</size><fields><field>\
+             <name>F</name>{}</field></fields></register></registers>\
+             </peripheral></peripherals></device>",
+            field
+        )
+    };
+    for field in &[
+        "<bitOffset>0</bitOffset><bitWidth>0</bitWidth>",
+        "<bitOffset>12</bitOffset><bitWidth>8</bitWidth>",
+        "<bitRange>[3:5]</bitRange>",
+        "<lsb>0</lsb><msb>16</msb>",
+    ] {
+        match devmem::svd::generate(&svd(field)) {
+            Err(devmem::svd::Error::Invalid(_)) => {}
+            other => panic!("{}: expected Error::Invalid, got {:?}", field, other),
+        }
+    }
+    assert!(devmem::svd::generate(&svd("<lsb>0</lsb><msb>15</msb>")).is_ok());
+}
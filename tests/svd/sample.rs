@@ -0,0 +1,151 @@
+// Register map of SAMPLE generated by devmem::svd. Do not edit.
+
+/// Serial port
+pub mod uart0 {
+    /// Base physical address of the peripheral
+    pub const BASE_ADDRESS: usize = 0x40001000;
+    /// Size of the address block of the peripheral
+    pub const SIZE: usize = 0x100;
+
+    /// Map the registers of the peripheral
+    ///
+    /// # Safety
+    ///
+    /// See `devmem::Mapping::new`.
+    pub unsafe fn map() -> devmem::Result<devmem::Mapping> {
+        devmem::Mapping::new(BASE_ADDRESS, SIZE)
+    }
+
+    devmem::register! {
+        /// Control register
+        pub mod ctrl: u32 @ 0x0, reset = 0x100, rw {
+            /// Enable the port
+            en: 0..=0,
+            // skipped: value LOOP = 0x5 of field MODE, which does not fit in the field
+            /// Operating mode
+            mode: 1..=2 => Mode {
+                Idle = 0x0,
+                /// Transmit only
+                Tx = 0x1,
+                Rx = 0x2,
+            },
+            div: 8..=15,
+        }
+    }
+
+    devmem::register! {
+        pub mod status: u16 @ 0x4, reset = 0x0, ro {
+            rxne: 0..=0,
+            // skipped: field rxne, as rxne is already defined
+        }
+    }
+
+    devmem::register! {
+        pub mod data0: u32 @ 0x10, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data1: u32 @ 0x14, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data2: u32 @ 0x18, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data3: u32 @ 0x1c, reset = 0x0, wo {
+        }
+    }
+
+    // skipped: register Ctrl at 0x20, as ctrl is already defined
+
+    // skipped: register WIDE has an unsupported size of 24 bits
+
+    devmem::register! {
+        pub mod cha_cfg: u8 @ 0x40, reset = 0x0, rw {
+        }
+    }
+
+    devmem::register! {
+        pub mod chb_cfg: u8 @ 0x50, reset = 0x0, rw {
+        }
+    }
+}
+
+/// Serial port
+pub mod uart1 {
+    /// Base physical address of the peripheral
+    pub const BASE_ADDRESS: usize = 0x40002000;
+    /// Size of the address block of the peripheral
+    pub const SIZE: usize = 0x100;
+
+    /// Map the registers of the peripheral
+    ///
+    /// # Safety
+    ///
+    /// See `devmem::Mapping::new`.
+    pub unsafe fn map() -> devmem::Result<devmem::Mapping> {
+        devmem::Mapping::new(BASE_ADDRESS, SIZE)
+    }
+
+    devmem::register! {
+        /// Control register
+        pub mod ctrl: u32 @ 0x0, reset = 0x100, rw {
+            /// Enable the port
+            en: 0..=0,
+            // skipped: value LOOP = 0x5 of field MODE, which does not fit in the field
+            /// Operating mode
+            mode: 1..=2 => Mode {
+                Idle = 0x0,
+                /// Transmit only
+                Tx = 0x1,
+                Rx = 0x2,
+            },
+            div: 8..=15,
+        }
+    }
+
+    devmem::register! {
+        pub mod status: u16 @ 0x4, reset = 0x0, ro {
+            rxne: 0..=0,
+            // skipped: field rxne, as rxne is already defined
+        }
+    }
+
+    devmem::register! {
+        pub mod data0: u32 @ 0x10, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data1: u32 @ 0x14, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data2: u32 @ 0x18, reset = 0x0, wo {
+        }
+    }
+
+    devmem::register! {
+        pub mod data3: u32 @ 0x1c, reset = 0x0, wo {
+        }
+    }
+
+    // skipped: register Ctrl at 0x20, as ctrl is already defined
+
+    // skipped: register WIDE has an unsupported size of 24 bits
+
+    devmem::register! {
+        pub mod cha_cfg: u8 @ 0x40, reset = 0x0, rw {
+        }
+    }
+
+    devmem::register! {
+        pub mod chb_cfg: u8 @ 0x50, reset = 0x0, rw {
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::sim::{Access, AccessKind, SimMemory};
+use devmem::trace::{self, Mismatch, Record, TraceReader, TraceWriter, Traced};
+use devmem::{Error, MemoryRead, MemoryWrite};
+use std::time::Duration;
+
+const BASE: u64 = 0x4000_0000;
+
+fn memory() -> SimMemory {
+    let mut memory = SimMemory::new();
+    memory.add_region(0x0, 0x100);
+    memory
+}
+
+/// Binary trace of a write and a read of each width
+fn recorded() -> (Vec<Record>, Vec<u8>) {
+    let mut traced = Traced::new(memory(), BASE, TraceWriter::new(Vec::new()).unwrap());
+    traced.write_u8(0x1, 0xa5).unwrap();
+    traced.write_u16(0x2, 0xbeef).unwrap();
+    traced.write_u32(0x4, 0xdead_beef).unwrap();
+    traced.write_u64(0x8, 0x0123_4567_89ab_cdef).unwrap();
+    traced.read_u8(0x1).unwrap();
+    traced.read_u16(0x2).unwrap();
+    traced.read_u32(0x4).unwrap();
+    traced.read_u64(0x8).unwrap();
+    let (_, writer) = traced.into_parts();
+    let bytes = writer.finish().unwrap();
+    let records = TraceReader::new(&bytes[..])
+        .unwrap()
+        .collect::<devmem::Result<Vec<Record>>>()
+        .unwrap();
+    (records, bytes)
+}
+
+fn parse_error(bytes: &[u8]) -> String {
+    match TraceReader::new(bytes).and_then(|reader| reader.collect::<devmem::Result<Vec<_>>>()) {
+        Err(Error::Parse(message)) => message,
+        other => panic!("expected Error::Parse, got {:?}", other),
+    }
+}
+
+#[test]
+fn writer_and_reader_round_trip() {
+    let (records, bytes) = recorded();
+    // Header, then 17 bytes and the value for each record
+    assert_eq!(bytes.len(), 8 + 8 * 17 + 2 * (1 + 2 + 4 + 8));
+
+    let accesses: Vec<Access> = records.iter().map(|&record| record.into()).collect();
+    let base = BASE as usize;
+    assert_eq!(
+        accesses,
+        [
+            Access::write(base + 0x1, 0xa5u8),
+            Access::write(base + 0x2, 0xbeefu16),
+            Access::write(base + 0x4, 0xdead_beefu32),
+            Access::write(base + 0x8, 0x0123_4567_89ab_cdefu64),
+            Access::read(base + 0x1, 0xa5u8),
+            Access::read(base + 0x2, 0xbeefu16),
+            Access::read(base + 0x4, 0xdead_beefu32),
+            Access::read(base + 0x8, 0x0123_4567_89ab_cdefu64),
+        ]
+    );
+    assert!(records
+        .windows(2)
+        .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+}
+
+#[test]
+fn reader_refuses_malformed_traces() {
+    let (_, bytes) = recorded();
+    assert_eq!(parse_error(b"DEVMEMT\x02"), "not a devmem trace");
+    assert!(matches!(TraceReader::new(&bytes[..4]), Err(Error::Io(_))));
+
+    // Cut in the fixed part and in the value of the last record
+    assert_eq!(
+        parse_error(&bytes[..bytes.len() - 10]),
+        "truncated trace record"
+    );
+    assert_eq!(
+        parse_error(&bytes[..bytes.len() - 1]),
+        "truncated trace record"
+    );
+
+    // The kind byte of the first record, with width 3
+    let mut invalid = bytes.clone();
+    invalid[8 + 16] = 0x83;
+    assert_eq!(parse_error(&invalid), "invalid access width 3");
+}
+
+#[test]
+fn replay_reports_mismatches() {
+    let (records, _) = recorded();
+    let mut memory = memory();
+    // The device returns the u32 written with the low bits cleared
+    memory.on_read(0x4, |stored: &mut u64| *stored & !0xf);
+    let mismatches = trace::replay(&mut memory, BASE, records.iter().copied()).unwrap();
+    assert_eq!(
+        mismatches,
+        [Mismatch {
+            record: records[6],
+            actual: 0xdead_bee0
+        }]
+    );
+    assert_eq!(memory.accesses().len(), records.len());
+}
+
+#[test]
+fn replay_refuses_invalid_widths() {
+    let mut memory = memory();
+    let record = Record {
+        timestamp: Duration::from_millis(1),
+        address: BASE + 0x8,
+        width: 3,
+        kind: AccessKind::Write,
+        value: u64::MAX,
+    };
+    assert!(matches!(
+        trace::replay(&mut memory, BASE, vec![record]),
+        Err(Error::Unsupported(_))
+    ));
+    assert!(memory.accesses().is_empty());
+    assert_eq!(memory.peek::<u64>(0x8).unwrap(), 0);
+}
@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::uio::{self, UioDevice, UioMap};
+use devmem::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use tempfile::TempDir;
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Write the attributes of a map, skipping the `None` ones like old kernels
+fn add_map(maps: &Path, index: usize, name: Option<&str>, size: u64, offset: Option<u64>) {
+    let map = maps.join(format!("map{}", index));
+    fs::create_dir_all(&map).unwrap();
+    fs::write(
+        map.join("addr"),
+        format!("0x{:016x}\n", 0xa000_0000u64 + (index as u64) * 0x1_0000),
+    )
+    .unwrap();
+    fs::write(map.join("size"), format!("0x{:016x}\n", size)).unwrap();
+    if let Some(name) = name {
+        fs::write(map.join("name"), format!("{}\n", name)).unwrap();
+    }
+    if let Some(offset) = offset {
+        fs::write(map.join("offset"), format!("0x{:x}\n", offset)).unwrap();
+    }
+}
+
+/// Temporary root with `sys/class/uio/uio{0,3}` and a plain file as
+/// `dev/uio3`, with a page for each of the maps of `uio3`
+fn uio_tree() -> TempDir {
+    let root = TempDir::new().unwrap();
+    let class = root.path().join("sys/class/uio");
+    fs::create_dir_all(class.join("uio0")).unwrap();
+    fs::write(class.join("uio0/name"), "other\n").unwrap();
+
+    let device = class.join("uio3");
+    let maps = device.join("maps");
+    fs::create_dir_all(&maps).unwrap();
+    fs::write(device.join("name"), "my-fpga\n").unwrap();
+    add_map(&maps, 10, Some("last"), 0x1000, Some(0));
+    add_map(&maps, 0, Some("registers"), 0x1000, Some(0));
+    add_map(&maps, 1, None, 0x1000, None);
+    add_map(&maps, 2, Some("unaligned"), 0x1000, Some(0x100));
+    add_map(&maps, 3, Some("broken"), 0x1000, Some(0x1000));
+    fs::create_dir_all(maps.join("not-a-map")).unwrap();
+
+    fs::create_dir_all(root.path().join("dev")).unwrap();
+    let file = File::create(root.path().join("dev/uio3")).unwrap();
+    file.set_len(4 * page_size() as u64).unwrap();
+    root
+}
+
+fn open(root: &TempDir) -> UioDevice {
+    UioDevice::open_in(root.path().join("sys"), root.path().join("dev"), 3).unwrap()
+}
+
+#[test]
+fn list_and_find_by_name() {
+    let root = uio_tree();
+    assert_eq!(
+        uio::list_in(root.path().join("sys")).unwrap(),
+        [(0, "other".to_string()), (3, "my-fpga".to_string())]
+    );
+    let device =
+        UioDevice::find_in(root.path().join("sys"), root.path().join("dev"), "my-fpga").unwrap();
+    assert_eq!((device.number(), device.name()), (3, "my-fpga"));
+    assert!(matches!(
+        UioDevice::find_in(root.path().join("sys"), root.path().join("dev"), "missing"),
+        Err(Error::Io(_))
+    ));
+    // uio0 has no device file
+    assert!(matches!(
+        UioDevice::open_in(root.path().join("sys"), root.path().join("dev"), 0),
+        Err(Error::Open(_))
+    ));
+}
+
+#[test]
+fn maps_are_sorted_with_optional_attributes() {
+    let root = uio_tree();
+    let maps = open(&root).maps().unwrap();
+    let summary: Vec<(usize, Option<&str>, u64)> = maps
+        .iter()
+        .map(|map| (map.index, map.name.as_deref(), map.offset))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (0, Some("registers"), 0),
+            (1, None, 0),
+            (2, Some("unaligned"), 0x100),
+            (3, Some("broken"), 0x1000),
+            (10, Some("last"), 0)
+        ]
+    );
+    assert_eq!(
+        maps[2],
+        UioMap {
+            index: 2,
+            name: Some("unaligned".to_string()),
+            addr: 0xa002_0000,
+            size: 0x1000,
+            offset: 0x100
+        }
+    );
+
+    fs::write(
+        root.path().join("sys/class/uio/uio3/maps/map1/size"),
+        "0xzz\n",
+    )
+    .unwrap();
+    assert!(matches!(open(&root).maps(), Err(Error::Parse(_))));
+}
+
+#[test]
+fn map_selects_the_region_by_page_and_skips_the_offset() {
+    let root = uio_tree();
+    let device = open(&root);
+    let mut map2 = unsafe { device.map(2).unwrap() };
+    // The mapping starts offset bytes into the page of the region, and is
+    // size - offset bytes long
+    map2.write_u8(0x0, 0x5a).unwrap();
+    map2.write_u32(0xefc, 0x1234_5678).unwrap();
+    assert!(matches!(
+        map2.read_u8(0xf00),
+        Err(Error::OutOfBounds { .. })
+    ));
+    let mut map0 = unsafe { device.map(0).unwrap() };
+    map0.write_u32(0x0, 0x9abc_def0).unwrap();
+    drop((map0, map2));
+
+    let data = fs::read(root.path().join("dev/uio3")).unwrap();
+    let end = 2 * page_size() + 0x1000;
+    assert_eq!(data[2 * page_size() + 0x100], 0x5a);
+    assert_eq!(&data[end - 4..end], &0x1234_5678u32.to_ne_bytes());
+    assert_eq!(&data[..4], &0x9abc_def0u32.to_ne_bytes());
+
+    assert!(matches!(unsafe { device.map(3) }, Err(Error::Parse(_))));
+    assert!(matches!(
+        unsafe { device.map(4) },
+        Err(Error::Unsupported(_))
+    ));
+}
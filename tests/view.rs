@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use devmem::{Error, Mapping};
+use std::fs;
+use tempfile::NamedTempFile;
+
+const LEN: usize = 0x1000;
+
+/// Temporary file of `LEN` zero bytes, and a mapping of the whole file
+fn mapped_file() -> (NamedTempFile, Mapping) {
+    let file = NamedTempFile::new().unwrap();
+    file.as_file().set_len(LEN as u64).unwrap();
+    let mapping = unsafe {
+        Mapping::builder()
+            .device(file.path())
+            .len(LEN)
+            .map()
+            .unwrap()
+    };
+    (file, mapping)
+}
+
+fn out_of_bounds<T: std::fmt::Debug>(result: devmem::Result<T>, offset: usize, len: usize) {
+    match result {
+        Err(Error::OutOfBounds {
+            offset: actual_offset,
+            len: actual_len,
+        }) => assert_eq!((actual_offset, actual_len), (offset, len)),
+        other => panic!("expected Error::OutOfBounds, got {:?}", other),
+    }
+}
+
+#[test]
+fn window_offsets_are_relative_to_the_window() {
+    let (file, mut mapping) = mapped_file();
+    mapping.write_u32(0x104, 0x1234_5678).unwrap();
+
+    let window = mapping.window(0x100, 0x10).unwrap();
+    assert_eq!(window.size(), 0x10);
+    assert_eq!(window.read_u32(0x4).unwrap(), 0x1234_5678);
+    out_of_bounds(window.read_u8(0x10), 0x10, 1);
+    out_of_bounds(window.read_u32(0xe), 0xe, 4);
+    let inner = window.window(0x4, 0x4).unwrap();
+    assert_eq!(inner.read_u32(0x0).unwrap(), 0x1234_5678);
+    out_of_bounds(window.window(0x8, 0x9).map(|_| ()), 0x8, 0x9);
+
+    let mut window = mapping.window_mut(0x200, 0x10).unwrap();
+    window.write_u64(0x8, 0x0102_0304_0506_0708).unwrap();
+    out_of_bounds(window.write_u8(0x10, 0), 0x10, 1);
+    out_of_bounds(window.write_u64(usize::MAX - 3, 0), usize::MAX - 3, 8);
+    let mut inner = window.window_mut(0x8, 0x8).unwrap();
+    inner.write_u16(0x6, 0xaaaa).unwrap();
+    out_of_bounds(inner.write_u16(0x8, 0), 0x8, 2);
+
+    out_of_bounds(mapping.window(LEN, 1).map(|_| ()), LEN, 1);
+    assert_eq!(mapping.window(LEN, 0).unwrap().size(), 0);
+    drop(mapping);
+
+    let data = fs::read(file.path()).unwrap();
+    assert_eq!(&data[0x208..0x210], &0xaaaa_0304_0506_0708u64.to_ne_bytes());
+}
+
+#[test]
+fn split_at_mut_bounds() {
+    let (_file, mut mapping) = mapped_file();
+    {
+        let (first, second) = mapping.split_at_mut(LEN).unwrap();
+        assert_eq!(first.size(), LEN);
+        assert_eq!(second.size(), 0);
+        out_of_bounds(second.read_u8(0), 0, 1);
+    }
+    out_of_bounds(mapping.split_at_mut(LEN + 1).map(|_| ()), 0, LEN + 1);
+
+    let (first, _) = mapping.split_at_mut(0x100).unwrap();
+    out_of_bounds(first.split_into(0x101).map(|_| ()), 0, 0x101);
+}
+
+#[test]
+fn split_halves_write_at_their_file_offsets() {
+    let (file, mut mapping) = mapped_file();
+    {
+        let (mut control, rest) = mapping.split_at_mut(0x100).unwrap();
+        let (mut status, mut data) = rest.split_into(0x10).unwrap();
+        assert_eq!(
+            (control.size(), status.size(), data.size()),
+            (0x100, 0x10, 0xef0)
+        );
+
+        control.write_u32(0xfc, 0x1111_1111).unwrap();
+        status.write_u32(0x0, 0x2222_2222).unwrap();
+        status.write_u32(0xc, 0x3333_3333).unwrap();
+        data.write_u32(0x0, 0x4444_4444).unwrap();
+        data.write_u32(0xeec, 0x5555_5555).unwrap();
+
+        // Neither half reaches into the other
+        out_of_bounds(control.write_u32(0x100, 0), 0x100, 4);
+        out_of_bounds(status.write_u32(0x10, 0), 0x10, 4);
+        out_of_bounds(data.write_u32(0xef0, 0), 0xef0, 4);
+
+        let (mut low, mut high) = data.split_at_mut(0x800).unwrap();
+        low.write_u8(0x7ff, 0x66).unwrap();
+        high.write_u8(0x0, 0x77).unwrap();
+    }
+    drop(mapping);
+
+    let data = fs::read(file.path()).unwrap();
+    let word = |offset: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_ne_bytes(bytes)
+    };
+    assert_eq!(word(0xfc), 0x1111_1111);
+    assert_eq!(word(0x100), 0x2222_2222);
+    assert_eq!(word(0x10c), 0x3333_3333);
+    assert_eq!(word(0x110), 0x4444_4444);
+    assert_eq!(word(0xffc), 0x5555_5555);
+    assert_eq!((data[0x90f], data[0x910]), (0x66, 0x77));
+}